@@ -7,8 +7,13 @@ use serenity::framework::StandardFramework;
 use std::collections::HashSet;
 
 pub mod checks;
+pub mod config;
+pub mod deck;
 pub mod dice;
+pub mod random;
 pub mod roles;
+pub mod systems;
+pub mod tables;
 
 pub mod prelude {
 	pub use super::checks::*;
@@ -118,33 +123,38 @@ async fn my_help(
 }
 
 pub async fn register(p0: StandardFramework) -> StandardFramework {
-	roles::register(dice::register(
-		p0
-			// Set a function to be called prior to each command execution. This
-			// provides the context of the command, the message that was received,
-			// and the full name of the command that will be called.
-			//
-			// You can not use this to determine whether a command should be
-			// executed. Instead, the `#[check]` macro gives you this functionality.
-			//
-			// **Note**: Async closures are unstable, you may use them in your
-			// application if you are fine using nightly Rust.
-			// If not, we need to provide the function identifiers to the
-			// hook-functions (before, after, normal, ...).
-			.before(before)
-			// Similar to `before`, except will be called directly _after_
-			// command execution.
-			.after(after)
-			// Set a function that's called whenever an attempted command-call's
-			// command could not be found.
-			.unrecognised_command(unknown_command)
-			// Set a function that's called whenever a command's execution didn't complete for one
-			// reason or another. For example, when a user has exceeded a rate-limit or a command
-			// can only be performed by the bot owner.
-			.on_dispatch_error(dispatch_error)
-			.group(&GENERAL_GROUP)
-			.help(&MY_HELP),
-	))
+	config::register(
+		roles::register(deck::register(tables::register(random::register(
+			systems::register(dice::register(
+				p0
+					// Set a function to be called prior to each command execution. This
+					// provides the context of the command, the message that was received,
+					// and the full name of the command that will be called.
+					//
+					// You can not use this to determine whether a command should be
+					// executed. Instead, the `#[check]` macro gives you this functionality.
+					//
+					// **Note**: Async closures are unstable, you may use them in your
+					// application if you are fine using nightly Rust.
+					// If not, we need to provide the function identifiers to the
+					// hook-functions (before, after, normal, ...).
+					.before(before)
+					// Similar to `before`, except will be called directly _after_
+					// command execution.
+					.after(after)
+					// Set a function that's called whenever an attempted command-call's
+					// command could not be found.
+					.unrecognised_command(unknown_command)
+					// Set a function that's called whenever a command's execution didn't complete for one
+					// reason or another. For example, when a user has exceeded a rate-limit or a command
+					// can only be performed by the bot owner.
+					.on_dispatch_error(dispatch_error)
+					.group(&GENERAL_GROUP)
+					.help(&MY_HELP),
+			)),
+		))))
+		.await,
+	)
 	.await
 }
 
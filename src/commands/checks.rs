@@ -116,3 +116,60 @@ async fn check_manage_roles_high(ctx: &Context, msg: &Message) -> Result<Option<
 		}
 	})
 }
+
+#[check]
+#[name = "ManageServer"]
+async fn manage_server(
+	ctx: &Context,
+	msg: &Message,
+	_: &mut Args,
+	_: &CommandOptions,
+) -> Result<(), Reason> {
+	match check_manage_server(ctx, msg).await {
+		Ok(None) => Ok(()),
+		Ok(Some(reason)) => Err(reason),
+		Err(err) => {
+			warn!(
+				"Manage server check failed for {} due to {:?}",
+				&msg.author.name, err
+			);
+			Err(Reason::UserAndLog {
+				user: msg.author.name.clone(),
+				log: err.to_string(),
+			})
+		}
+	}
+}
+
+// Server wide settings like named dice, moves and tables can only be changed by the owner,
+// or members with a role which can manage the server
+async fn check_manage_server(ctx: &Context, msg: &Message) -> Result<Option<Reason>> {
+	let Some(guild) = msg.guild(&ctx).await else {
+		return Ok(Some(Reason::UserAndLog {
+			user: msg.author.name.clone(),
+			log: "Not in a guild".to_string(),
+		}));
+	};
+	if guild.owner_id == msg.author.id {
+		return Ok(None);
+	}
+
+	let member = guild.member(&ctx, msg.author.id).await?;
+	for x in &member.roles {
+		let role = guild
+			.roles
+			.get(x)
+			.ok_or_else(|| anyhow!("Couldn't find role {} in {}", x, guild.name))?;
+		if role.has_permission(Permissions::ADMINISTRATOR)
+			|| role.has_permission(Permissions::MANAGE_GUILD)
+		{
+			return Ok(None);
+		}
+	}
+
+	info!("Failed manage server check");
+	Ok(Some(Reason::UserAndLog {
+		user: msg.author.name.clone(),
+		log: "Manage server permission is needed to change this server's settings".to_string(),
+	}))
+}
@@ -0,0 +1,305 @@
+use super::prelude::*;
+use crate::rolls::Settings;
+use crate::SettingsData;
+use anyhow::{anyhow, Result};
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::StandardFramework;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub async fn register(framework: StandardFramework) -> StandardFramework {
+	framework
+		.group(&CONFIG_GROUP)
+		.bucket("CONFIG_BUCKET", |b| b.time_span(60).limit(10))
+		.await
+}
+
+pub struct Persistent {
+	guild_data: Arc<RwLock<HashMap<GuildId, Settings>>>,
+}
+
+impl Default for Persistent {
+	fn default() -> Self {
+		Self {
+			guild_data: Arc::new(RwLock::default()),
+		}
+	}
+}
+
+impl Persistent {
+	async fn from_context(ctx: &Context) -> Arc<Self> {
+		ctx.data
+			.read()
+			.await
+			.get::<SettingsData>()
+			.expect("SettingsData is initialised at start")
+			.clone()
+	}
+
+	async fn get_guild_data(&self, id: GuildId) -> Result<Settings> {
+		{
+			if let Some(data) = self.guild_data.read().await.get(&id) {
+				return Ok(data.clone());
+			}
+		}
+
+		let loaded = crate::store::load_data::<Settings>(&id.0.to_string()).await?;
+
+		Ok({
+			let mut dat = self.guild_data.write().await;
+			dat.entry(id).or_insert_with(|| loaded).clone()
+		})
+	}
+
+	async fn set_guild_data(&self, id: GuildId, data: Settings) {
+		let _ = crate::store::save_data::<Settings>(&id.0.to_string(), data.clone());
+
+		{
+			let mut dat = self.guild_data.write().await;
+			dat.insert(id, data);
+		}
+	}
+}
+
+// Roll settings for the guild a message was sent in. DMs use the defaults.
+pub async fn settings(ctx: &Context, msg: &Message) -> Result<Settings> {
+	match msg.guild_id {
+		Some(guild_id) => {
+			Persistent::from_context(ctx)
+				.await
+				.get_guild_data(guild_id)
+				.await
+		}
+		None => Ok(Settings::default()),
+	}
+}
+
+async fn update_settings(
+	ctx: &Context,
+	msg: &Message,
+	update: impl FnOnce(&mut Settings) -> Result<()>,
+) -> Result<()> {
+	let guild_id = msg.guild_id.ok_or_else(|| anyhow!("Must be in a guild"))?;
+	let persistent = Persistent::from_context(ctx).await;
+	let mut cfg = persistent.get_guild_data(guild_id).await?;
+	update(&mut cfg)?;
+	persistent.set_guild_data(guild_id, cfg).await;
+	Ok(())
+}
+
+#[group]
+#[prefix(config)]
+#[commands(
+	add_dice,
+	remove_dice,
+	list_dice,
+	add_move,
+	remove_move,
+	list_moves,
+	crits,
+	fate
+)]
+struct Config;
+
+#[command]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description(r#"Adds a named dice for this server, rolled like 3dHit. Names must be letters starting with a capital.
+
+Faces are separated by commas. A face is a number, a symbol, or a symbol with a value like crit=2. Symbols without a value are worth 0.
+Rolls of dice with symbols show how many of each symbol came up.
+"#)]
+#[usage("Hit miss, miss, hit=1, hit=1, crit=2, 0")]
+#[bucket = "CONFIG_BUCKET"]
+async fn add_dice(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+	args.trimmed();
+	let name = args
+		.single::<String>()
+		.map_err(|_| anyhow!("Missing dice name"))?;
+	let faces = args.rest();
+
+	update_settings(ctx, msg, |cfg| cfg.add_dice(&name, faces)).await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Added d{}: {{{}}}", name, faces.trim()))
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description("Removes a named dice from this server")]
+#[usage("Hit")]
+#[bucket = "CONFIG_BUCKET"]
+async fn remove_dice(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let name = args.message().trim();
+
+	update_settings(ctx, msg, |cfg| {
+		cfg.dice
+			.remove(name)
+			.map(|_| ())
+			.ok_or_else(|| anyhow!("No dice named {}", name))
+	})
+	.await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Removed d{name}"))
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Lists this server's named dice")]
+#[usage("")]
+async fn list_dice(ctx: &Context, msg: &Message) -> CommandResult {
+	use itertools::Itertools;
+
+	let cfg = settings(ctx, msg).await?;
+	let result = if cfg.dice.is_empty() {
+		"No named dice. Add one with config add_dice.".to_string()
+	} else {
+		cfg.dice
+			.iter()
+			.map(|(name, faces)| format!("d{}: {{{}}}", name, faces.iter().join(", ")))
+			.join("\n")
+	};
+	msg.channel_id.say(&ctx.http, result).await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description(r#"Adds a move for this server, rolled with d;move name. Names must be letters, and replace a built in move of the same name.
+
+Give the dice then comma separated bands. {} at the start of the dice is a pool size, like {}d6kh1, and anywhere else is a modifier like +2, which otherwise goes after the dice.
+Pool dice can be followed by | and the dice for a pool of 0, like {}d6kh1|2d6kl1.
+A band is a total like 6, a range like 7-9, at most like 6- or at least like 10+.
+A face and a count like 6x2 is a crit, which beats the bands when that many dice show the face.
+"#)]
+#[usage(
+	"blades {}d6kh1|2d6kl1 1-3: Bad outcome, 4-5: Partial success, 6: Full success, 6x2: Critical"
+)]
+#[bucket = "CONFIG_BUCKET"]
+async fn add_move(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+	args.trimmed();
+	let name = args
+		.single::<String>()
+		.map_err(|_| anyhow!("Missing move name"))?;
+	let spec = args.rest();
+
+	update_settings(ctx, msg, |cfg| cfg.add_move(&name, spec)).await?;
+	msg.channel_id
+		.say(
+			&ctx.http,
+			format!("Added move {}: {}", name.to_lowercase(), spec.trim()),
+		)
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description("Removes a move from this server")]
+#[usage("blades")]
+#[bucket = "CONFIG_BUCKET"]
+async fn remove_move(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let name = args.message().trim().to_lowercase();
+
+	update_settings(ctx, msg, |cfg| {
+		cfg.moves
+			.remove(&name)
+			.map(|_| ())
+			.ok_or_else(|| anyhow!("No move named {}", name))
+	})
+	.await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Removed move {name}"))
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description("Lists the moves d;move can roll on this server")]
+#[usage("")]
+async fn list_moves(ctx: &Context, msg: &Message) -> CommandResult {
+	use itertools::Itertools;
+
+	let cfg = settings(ctx, msg).await?;
+	let result = cfg
+		.all_moves()
+		.iter()
+		.map(|(name, to_roll)| format!("{name}: {to_roll}"))
+		.join("\n");
+	msg.channel_id.say(&ctx.http, result).await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description(r#"Turns crits on or off for this server. With crits on, d20s crit on a natural 20 and fumble on a natural 1, unless the roll sets its own range with cs and cf.
+
+Crits and fumbles are shown in bold, and called out after the result.
+"#)]
+#[usage("on")]
+#[bucket = "CONFIG_BUCKET"]
+async fn crits(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let on = match args.message().trim().to_ascii_lowercase().as_str() {
+		"on" => true,
+		"off" => false,
+		other => return Err(anyhow!("Expected on or off. Got: {}", other).into()),
+	};
+
+	update_settings(ctx, msg, |cfg| {
+		cfg.crits = on;
+		Ok(())
+	})
+	.await?;
+	msg.channel_id
+		.say(
+			&ctx.http,
+			format!("Crits turned {}", if on { "on" } else { "off" }),
+		)
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description(r#"Sets which Fate dice dF rolls for this server. standard rolls -1, 0 and +1, scaled rolls -3, 0 and +3.
+
+dF3 always rolls the scaled dice.
+"#)]
+#[usage("scaled")]
+#[bucket = "CONFIG_BUCKET"]
+async fn fate(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let scaled = match args.message().trim().to_ascii_lowercase().as_str() {
+		"standard" => false,
+		"scaled" => true,
+		other => return Err(anyhow!("Expected standard or scaled. Got: {}", other).into()),
+	};
+
+	update_settings(ctx, msg, |cfg| {
+		cfg.scaled_fate = scaled;
+		Ok(())
+	})
+	.await?;
+	msg.channel_id
+		.say(
+			&ctx.http,
+			format!(
+				"dF now rolls {}",
+				if scaled {
+					"-3, 0 and +3"
+				} else {
+					"-1, 0 and +1"
+				}
+			),
+		)
+		.await?;
+	Ok(())
+}
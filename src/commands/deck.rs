@@ -0,0 +1,164 @@
+use super::prelude::*;
+use crate::rolls::{Deck, DeckKind};
+use crate::DeckData;
+use anyhow::{anyhow, Result};
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::StandardFramework;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn register(framework: StandardFramework) -> StandardFramework {
+	framework.group(&DECKS_GROUP)
+}
+
+pub struct Persistent {
+	channel_data: Arc<RwLock<HashMap<ChannelId, Deck>>>,
+}
+
+impl Default for Persistent {
+	fn default() -> Self {
+		Self {
+			channel_data: Arc::new(RwLock::default()),
+		}
+	}
+}
+
+impl Persistent {
+	async fn from_context(ctx: &Context) -> Arc<Self> {
+		ctx.data
+			.read()
+			.await
+			.get::<DeckData>()
+			.expect("DeckData is initialised at start")
+			.clone()
+	}
+
+	// Loads, changes and saves a channel's deck while holding the lock, so two draws at
+	// once can't take the same card. The deck is only changed if the update succeeds.
+	async fn update_channel_data<T>(
+		&self,
+		id: ChannelId,
+		update: impl FnOnce(&mut Deck) -> Result<T>,
+	) -> Result<T> {
+		let mut dat = self.channel_data.write().await;
+		let deck = match dat.entry(id) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			Entry::Vacant(entry) => {
+				entry.insert(crate::store::load_data::<Deck>(&id.0.to_string()).await?)
+			}
+		};
+		let mut updated = deck.clone();
+		let result = update(&mut updated)?;
+		// saves finish in order while the lock is held
+		crate::store::save_data::<Deck>(&id.0.to_string(), updated.clone()).await?;
+		*deck = updated;
+		Ok(result)
+	}
+}
+
+// Changes the deck of the channel a message was sent in, saving it if the change succeeds
+async fn update_deck<T>(
+	ctx: &Context,
+	msg: &Message,
+	update: impl FnOnce(&mut Deck) -> Result<T>,
+) -> Result<T> {
+	Persistent::from_context(ctx)
+		.await
+		.update_channel_data(msg.channel_id, update)
+		.await
+}
+
+// How many cards to draw or peek at, 1 unless given
+fn card_count(args: &Args) -> Result<usize> {
+	let count = args.message().trim();
+	if count.is_empty() {
+		return Ok(1);
+	}
+	count
+		.parse()
+		.map_err(|e| anyhow!("Can't parse number of cards {}, {}", count, e))
+}
+
+// A deck of cards for each channel, which are drawn without replacement
+#[group]
+#[prefix(deck)]
+#[default_command(draw)]
+#[commands(shuffle, draw, peek, return_cards)]
+struct Decks;
+
+#[command]
+#[description(r#"Puts every card back in this channel's deck and shuffles it.
+
+Give standard for 52 cards, jokers for 54 cards with a red and black joker, tarot for 78 tarot cards, or cards separated by commas for a custom deck.
+Without one, the channel's current deck is shuffled. Channels start with a standard deck.
+"#)]
+#[usage("jokers")]
+async fn shuffle(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let kind = match args.message().trim() {
+		"" => None,
+		kind => Some(DeckKind::parse(kind)?),
+	};
+	let size = update_deck(ctx, msg, |deck| {
+		deck.shuffle(kind, &mut rand::thread_rng());
+		Ok(deck.pile.len())
+	})
+	.await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Shuffled {size} cards"))
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[description("Draws cards from this channel's deck, which stay out of it until they're returned or the deck is shuffled")]
+#[usage("3")]
+async fn draw(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let count = card_count(&args)?;
+	let result = update_deck(ctx, msg, |deck| {
+		let cards = deck.draw(count, &mut rand::thread_rng())?;
+		Ok(deck.describe(&cards))
+	})
+	.await?;
+	msg.reply(&ctx.http, format!("drew {result}")).await?;
+	Ok(())
+}
+
+#[command]
+#[description("Shows the next cards in this channel's deck without drawing them")]
+#[usage("3")]
+async fn peek(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let count = card_count(&args)?;
+	let result = update_deck(ctx, msg, |deck| {
+		let cards = deck.peek(count, &mut rand::thread_rng())?.to_vec();
+		Ok(deck.describe(&cards))
+	})
+	.await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Next: {result}"))
+		.await?;
+	Ok(())
+}
+
+#[command("return")]
+#[description("Shuffles drawn cards back into this channel's deck. Give cards separated by commas to return only those.")]
+#[usage("Red Joker, A♠")]
+async fn return_cards(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let cards: Vec<&str> = args
+		.message()
+		.split(',')
+		.filter(|card| !card.trim().is_empty())
+		.collect();
+	let (count, left) = update_deck(ctx, msg, |deck| {
+		let count = deck.return_cards(&cards, &mut rand::thread_rng())?;
+		Ok((count, deck.pile.len()))
+	})
+	.await?;
+	msg.channel_id
+		.say(
+			&ctx.http,
+			format!("Returned {count} cards ({left} in the deck)"),
+		)
+		.await?;
+	Ok(())
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use regex::{Captures, Regex};
 
 use super::prelude::*;
+use crate::rolls::Settings;
 use serenity::constants::MESSAGE_CODE_LIMIT;
 use serenity::framework::standard::{Args, CommandResult};
 use serenity::framework::StandardFramework;
@@ -11,7 +12,7 @@ pub fn register(framework: StandardFramework) -> StandardFramework {
 }
 
 #[group]
-#[commands(inline, roll, roll_many, roll_bincount)]
+#[commands(inline, roll, roll_many, roll_bincount, odds)]
 struct Dice;
 
 #[command]
@@ -20,22 +21,79 @@ struct Dice;
 
 1d20 rolls a single d20.
 2d10 rolls two d10s.
-4dF rolls four MfD Fudge dice (values [-3, 0, 3])
+4dF rolls four Fate dice (values -1, 0 and +1, shown as + − ▢) and gives the total on the Fate ladder, eg Great (+4). 4dF3 rolls MfD Fudge dice (values -3, 0 and 3), which servers can make the default with config fate scaled.
+1d% or d% rolls percentile dice, showing the tens and units dice. Add b and a number for bonus dice or p for penalty dice, eg 1d%b1 rolls an extra tens dice and keeps the better result.
 (3d3 * 2) + 1d10 rolls 3d3s, doubles them, then adds a d10.
-10d20<15 rolls 10d20 then filters only the rolls <15.
+10d20<15 rolls 10d20 then filters only the rolls <15. Filters, rerolls, counting and explosions can compare with <, <=, >, >=, = and <> (not equal), eg 10d20>=15.
 10d20! uses exploding dice. A roll of max value (20 in this case) will cause another roll to be made.
-10d20!!>20 uses compounding exploding dice, and requires each roll to be >20. A roll of max value will cause another roll to be made and adds it to that dice, rather than treating it as a separate dice. It's possible for this roll to have a non-zero result.
+1d20+3 adv rolls the 1d20 twice and keeps the higher, dis keeps the lower. adv or dis alone rolls 2d20 keeping one.
+4d6kh3 rolls 4d6 and keeps the highest 3. kl keeps the lowest, dh and dl drop the highest or lowest instead (eg 4d6dl1).
+2d6r<3 rerolls any 1s or 2s once, keeping the new roll. 1d10rr1 keeps rerolling 1s until the dice isn't a 1. r1 rerolls only 1s, r>5 rerolls values above 5.
+10d10>7s counts the dice which pass the filters as successes instead of summing them. Add f1 to subtract a success for each 1 rolled (eg 10d10>7sf1), a roll with failures and no successes is a botch.
+1d20cs>19cf<3 marks natural 20s and 19s as crits and 1s and 2s as fumbles, in bold with the result called out. Servers can turn on crits for d20s with config crits on.
+10d20>15!! uses compounding exploding dice, and requires each roll to be >15. A roll of max value will cause another roll to be made and adds it to that dice, rather than treating it as a separate dice.
+1d10!>8 explodes on 9s and 10s, !=1 explodes on 1s. !p penetrates, each extra dice is 1 lower. Add l and a number to limit how many extra dice explosions can roll (eg 5d6!l3). Dice added by explosions are marked with !.
+(1d4)d6 and 1d(2d6) roll dice with a computed count or number of sides, showing each step.
+3d{1,1,2,3,5,8} rolls dice with the listed faces. Servers can add named dice like 3dHit with config add_dice.
+Expressions support + - * / % ^, parentheses and the functions abs, floor, ceil, round, sqrt, min and max, eg max(1d20, 1d20) + 5. Results are whole numbers, rounded down like most tabletop rules, so 7 / 2 is 3. Fractions are kept until the end, so ceil(7 / 2) is 4 and round(1d6 / 2) rounds to nearest. // divides and rounds down straight away, eg 1d20 // 2.
+Label parts of a roll with [brackets] after them, and add a comment after #, eg 1d20+5 [to hit] + 1d4 [bless] # attack the goblin.
+Compare a roll against a target with vs, <=, <, >= or >, eg 1d20+5 vs 15 or 1d100 <= 45, to see whether it succeeded and by how much. A roll of only one die like 1d100<=45 compares against a target without spaces too. Anywhere else, like 10d10>=6 or 1d20>10 + 1d6, the comparison filters the dice. Rolling under a target also gives hard (half) and extreme (fifth) successes.
+Roll several expressions at once by separating them with ; or , eg 1d20+7; 2d6+4. Each gets its own line.
+6x(4d6kh3) or repeat(4d6kh3, 6) rolls an expression several times, giving a list. sum, min, max and sort work on lists, eg sum(3x(1d20)). Repeats are limited to 100 in total, counting repeats inside repeats, and results to three discord messages.
 Combine these options as you wish. It should hopefully work.
 "#)]
 #[usage("5d20")]
 async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
 	let arg = args.message();
 	let arg = if arg.is_empty() { "1d20" } else { arg };
-	let result = crate::rolls::roll_expression(arg)?;
-	msg.channel_id.say(&ctx.http, result).await?;
+	let settings = super::config::settings(ctx, msg).await?;
+	let result = crate::rolls::roll_expression(arg, &settings)?;
+	for message in into_messages(&result)? {
+		msg.channel_id.say(&ctx.http, message.trim()).await?;
+	}
 	Ok(())
 }
 
+// Splits results into at most three discord messages, breaking lines which are too long between rolls
+fn into_messages(result: &str) -> Result<Vec<String>> {
+	use anyhow::ensure;
+
+	let mut messages = vec![];
+	let mut message = String::new();
+	for line in result.lines() {
+		let mut line = line;
+		while !line.is_empty() {
+			let mut end = line.len().min(MESSAGE_CODE_LIMIT - 1);
+			while !line.is_char_boundary(end) {
+				end -= 1;
+			}
+			if end < line.len() {
+				end = line[..end]
+					.rfind("; ")
+					.or_else(|| line[..end].rfind(", "))
+					.map_or(end, |idx| idx + 1);
+			}
+			let (next, rest) = line.split_at(end);
+			line = rest;
+			if message.len() + next.len() + 1 >= MESSAGE_CODE_LIMIT {
+				messages.push(message);
+				message = String::new();
+			}
+			message += next;
+			message.push('\n');
+		}
+	}
+	if !message.is_empty() {
+		messages.push(message);
+	}
+
+	ensure!(
+		messages.len() <= 3,
+		"Results are limited to what fits into three discord messages (6000 characters)"
+	);
+	Ok(messages)
+}
+
 #[command]
 #[aliases(rm)]
 #[description(r#"Rolls a dice many times. Use like d;roll but with a multiple at the start.
@@ -49,6 +107,7 @@ You can roll_many a maximum of 100 times. A further limit of at most three disco
 #[usage("10 5d20")]
 async fn roll_many(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 	use anyhow::anyhow;
+	use std::fmt::Write;
 
 	let count: u32 = args.parse().map_err(|x| {
 		anyhow!(
@@ -64,31 +123,19 @@ async fn roll_many(ctx: &Context, msg: &Message, mut args: Args) -> CommandResul
 	}
 	let arg = args.rest();
 	let arg = if arg.is_empty() { "1d20" } else { arg };
+	let settings = super::config::settings(ctx, msg).await?;
 
 	let mut result = "".to_string();
-	let mut messages = vec![];
-
 	for i in 1..=count {
-		let next_line = &format!("{}: {}\n", i, crate::rolls::roll_expression(arg)?);
-		if result.len() + next_line.len() >= MESSAGE_CODE_LIMIT {
-			messages.push(result);
-			result = "".to_string();
-		}
-		result += next_line;
-	}
-
-	if !result.is_empty() {
-		messages.push(result);
-	}
-
-	if messages.len() > 3 {
-		return Err(anyhow!(
-			"Roll many is limited to displaying results which fit into three discord messages"
-		)
-		.into());
+		writeln!(
+			result,
+			"{}: {}",
+			i,
+			crate::rolls::roll_expression(arg, &settings)?
+		)?;
 	}
 
-	for message in messages {
+	for message in into_messages(&result)? {
 		msg.channel_id.say(&ctx.http, message.trim()).await?;
 	}
 
@@ -126,10 +173,13 @@ async fn roll_bincount(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
 	}
 	let arg = args.rest();
 	let arg = if arg.is_empty() { "1d20" } else { arg };
+	let settings = super::config::settings(ctx, msg).await?;
 	let mut counts: HashMap<DiceInt, DiceInt> = HashMap::new();
 
 	for _ in 0..count {
-		let entry = counts.entry(roll_expression_value(arg)?).or_default();
+		let entry = counts
+			.entry(roll_expression_value(arg, &settings)?)
+			.or_default();
 		*entry += 1;
 	}
 
@@ -156,17 +206,124 @@ async fn roll_bincount(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
 	Ok(())
 }
 
+#[command]
+#[aliases(dist)]
+#[description(r#"Works out the exact odds of each result of a roll, rather than sampling it like d;roll_bincount.
+
+d;odds 2d6+3
+
+Shows the mean, standard deviation and percentiles, and the chance of rolling each result or higher.
+Add >= or vs and a number to the end to just get the chance of rolling at least that number, eg d;odds 1d20+5 vs 15
+
+Supports the same options as d;roll, except keeping or dropping dice alongside explosions which add dice or have a limit, like 4d6!kh3 or 5d6!!l3kh3. Explosions are followed until further explosions are vanishingly unlikely, and a limit like 5d6!l3 is shared by every dice.
+"#)]
+#[usage("2d6+3")]
+async fn odds(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let arg = args.message();
+	let arg = if arg.is_empty() { "1d20" } else { arg };
+
+	// big expressions can take a while to work out
+	let arg = arg.to_string();
+	let settings = super::config::settings(ctx, msg).await?;
+	let messages = tokio::task::spawn_blocking(move || describe_odds(&arg, &settings)).await??;
+
+	for message in messages {
+		msg.channel_id.say(&ctx.http, message.trim()).await?;
+	}
+
+	Ok(())
+}
+
+// Shows at most this many results individually
+const MAX_ODDS_TABLE_ROWS: usize = 50;
+
+fn describe_odds(arg: &str, settings: &Settings) -> Result<Vec<String>> {
+	use anyhow::anyhow;
+	use std::fmt::Write;
+
+	let (expression, at_least) = match arg.rsplit_once(" >=").or_else(|| arg.rsplit_once(" vs ")) {
+		Some((expression, at_least)) => (
+			expression,
+			Some(
+				at_least
+					.trim()
+					.parse::<f64>()
+					.map_err(|e| anyhow!("Can't parse {}, {}", at_least.trim(), e))?,
+			),
+		),
+		None => (arg, None),
+	};
+
+	let dist = crate::rolls::expression_distribution(expression, settings)?;
+
+	if let Some(at_least) = at_least {
+		return Ok(vec![format!(
+			"`{}` >= {}: **{:.2}%**",
+			expression.trim(),
+			at_least,
+			dist.at_least(at_least) * 100.0
+		)]);
+	}
+
+	let mut result = format!(
+		"`{}`\nMean **{:.2}**, standard deviation **{:.2}**, range {} to {}\nPercentiles: {}\n",
+		expression.trim(),
+		dist.mean(),
+		dist.std_dev(),
+		dist.min(),
+		dist.max(),
+		[5, 25, 50, 75, 95]
+			.iter()
+			.map(|percent| format!(
+				"{}%: {}",
+				percent,
+				dist.percentile(f64::from(*percent) / 100.0)
+			))
+			.collect::<Vec<String>>()
+			.join(", ")
+	);
+
+	if dist.possible_results() > MAX_ODDS_TABLE_ROWS {
+		write!(
+			result,
+			"{} possible results, too many to list. Add >= and a number to get the chance of a specific result.",
+			dist.possible_results()
+		)?;
+		return Ok(vec![result]);
+	}
+
+	let mut messages = vec![];
+	result += "```\nresult  chance  or higher\n";
+	for (value, probability) in dist.outcomes() {
+		let next_line = format!(
+			"{:<7} {:>6.2}% {:>7.2}%\n",
+			value,
+			probability * 100.0,
+			dist.at_least(value) * 100.0
+		);
+		if result.len() + next_line.len() + 3 >= MESSAGE_CODE_LIMIT {
+			messages.push(result + "```");
+			result = "```\n".to_string();
+		}
+		result += &next_line;
+	}
+	messages.push(result + "```");
+
+	Ok(messages)
+}
+
 #[command]
 #[aliases(i)]
 #[description("Inline rolls in a longer message. Repeats your message back to you with rolls in [[brackets]] replaced with the result of the roll.")]
 #[usage("I attack the dragon [[2d20>15]].")]
 async fn inline(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-	let result = inline_rolls(msg, args.message()).await;
+	let settings = super::config::settings(ctx, msg).await?;
+	let result = inline_rolls(msg, args.message(), &settings).await;
 	msg.channel_id.say(&ctx.http, result?).await?;
 	Ok(())
 }
 
-async fn inline_rolls(msg: &Message, message: &str) -> Result<String> {
+async fn inline_rolls(msg: &Message, message: &str, settings: &Settings) -> Result<String> {
 	lazy_static! {
 		static ref ROLL_REGEX: Regex = Regex::new(r"\[\[([^\]]+)\]\]").expect("Hardcoded regex");
 	}
@@ -178,7 +335,7 @@ async fn inline_rolls(msg: &Message, message: &str) -> Result<String> {
 	let rolled =
 		ROLL_REGEX.replace_all(
 			message,
-			|caps: &Captures| match crate::rolls::roll_expression(&caps[1]) {
+			|caps: &Captures| match crate::rolls::roll_expression(&caps[1], settings) {
 				Ok(rolled) => rolled,
 				Err(e) => {
 					err = Some(e);
@@ -0,0 +1,66 @@
+use super::prelude::*;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::StandardFramework;
+
+pub fn register(framework: StandardFramework) -> StandardFramework {
+	framework.group(&RANDOM_GROUP)
+}
+
+// Randomness which isn't dice, like picking from a list
+#[group]
+#[commands(choose, shuffle, flip)]
+struct Random;
+
+#[command]
+#[aliases(pick)]
+#[description(
+	r#"Picks one of a list of items, separated by commas or spaces.
+
+Give an item a weight like 3:sword to make it 3 times as likely as an item without one.
+"#
+)]
+#[usage("3:sword, 2:shield, potion")]
+async fn choose(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let choice = crate::rolls::choose(args.message())?;
+	msg.reply(&ctx.http, format!("chose **{choice}**")).await?;
+	Ok(())
+}
+
+#[command]
+#[aliases(order)]
+#[description(
+	"Puts a list of items, separated by commas or spaces, in a random order, eg for turn order"
+)]
+#[usage("Alice Bob Carol")]
+async fn shuffle(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	use itertools::Itertools;
+
+	let order = crate::rolls::shuffle(args.message())?;
+	msg.channel_id
+		.say(
+			&ctx.http,
+			order
+				.iter()
+				.enumerate()
+				.map(|(i, item)| format!("{}. {}", i + 1, item))
+				.join("\n"),
+		)
+		.await?;
+	Ok(())
+}
+
+#[command]
+#[aliases(coin)]
+#[description("Flips one coin, or as many as given")]
+#[usage("10")]
+async fn flip(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let count = match args.message().trim() {
+		"" => 1,
+		count => count
+			.parse()
+			.map_err(|e| anyhow::anyhow!("Can't parse number of coins {}, {}", count, e))?,
+	};
+	let result = crate::rolls::flip(count)?;
+	msg.reply(&ctx.http, result.to_markdown()).await?;
+	Ok(())
+}
@@ -0,0 +1,61 @@
+use super::prelude::*;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::StandardFramework;
+
+pub fn register(framework: StandardFramework) -> StandardFramework {
+	framework.group(&SYSTEMS_GROUP)
+}
+
+// Rolls for particular game systems
+#[group]
+#[commands(sw, narrative, roll_move)]
+struct Systems;
+
+#[command]
+#[aliases(savage)]
+#[description(r#"Makes a Savage Worlds trait roll.
+
+d;sw d8+1 rolls a d8 trait dice and a d6 wild dice, both exploding, and keeps the higher before adding 1.
+The target is 4 unless you give one, eg d;sw d10-2 vs 6. Each 4 over the target is a raise.
+Both dice rolling a natural 1 is a critical failure.
+"#)]
+#[usage("d8+1")]
+async fn sw(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let arg = args.message();
+	let arg = if arg.is_empty() { "d4" } else { arg };
+	let settings = super::config::settings(ctx, msg).await?;
+	let result = crate::rolls::savage_worlds(arg, &settings)?;
+	msg.channel_id.say(&ctx.http, result.to_markdown()).await?;
+	Ok(())
+}
+
+#[command]
+#[aliases(genesys, ffg)]
+#[description(r#"Rolls a pool of Genesys or Star Wars FFG narrative dice.
+
+d;narrative 2a1p1d1c rolls 2 ability, 1 proficiency, 1 difficulty and 1 challenge dice.
+b is boost, s setback, a ability, d difficulty, p proficiency and c challenge.
+Successes cancel failures and advantages cancel threats. Triumphs count as a success and despairs as a failure, but both are always shown.
+"#)]
+#[usage("2a1p1d1c")]
+async fn narrative(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let result = crate::rolls::narrative(args.message())?;
+	msg.channel_id.say(&ctx.http, result.to_markdown()).await?;
+	Ok(())
+}
+
+#[command("move")]
+#[aliases(pbta)]
+#[description(r#"Rolls a move and says which band the total lands in.
+
+d;move +2 rolls 2d6+2 for a Powered by the Apocalypse move: 6 or less is a miss, 7-9 a weak hit and 10+ a strong hit.
+d;move blades 3 rolls 3d6 for Blades in the Dark, keeping the highest: 1-3 is a bad outcome, 4-5 a partial success, 6 a full success and two or more sixes a critical. A pool of 0 rolls 2d6 and keeps the lowest, which can't crit.
+Servers can add their own moves with config add_move.
+"#)]
+#[usage("+2")]
+async fn roll_move(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let settings = super::config::settings(ctx, msg).await?;
+	let result = crate::rolls::roll_move(args.message(), &settings)?;
+	msg.channel_id.say(&ctx.http, result.to_markdown()).await?;
+	Ok(())
+}
@@ -0,0 +1,181 @@
+use super::prelude::*;
+use crate::rolls::Tables;
+use crate::TableData;
+use anyhow::{anyhow, Result};
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::framework::StandardFramework;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub fn register(framework: StandardFramework) -> StandardFramework {
+	framework.group(&TABLE_GROUP)
+}
+
+pub struct Persistent {
+	guild_data: Arc<RwLock<HashMap<GuildId, Tables>>>,
+}
+
+impl Default for Persistent {
+	fn default() -> Self {
+		Self {
+			guild_data: Arc::new(RwLock::default()),
+		}
+	}
+}
+
+impl Persistent {
+	async fn from_context(ctx: &Context) -> Arc<Self> {
+		ctx.data
+			.read()
+			.await
+			.get::<TableData>()
+			.expect("TableData is initialised at start")
+			.clone()
+	}
+
+	async fn get_guild_data(&self, id: GuildId) -> Result<Tables> {
+		{
+			if let Some(data) = self.guild_data.read().await.get(&id) {
+				return Ok(data.clone());
+			}
+		}
+
+		let loaded = crate::store::load_data::<Tables>(&id.0.to_string()).await?;
+
+		Ok({
+			let mut dat = self.guild_data.write().await;
+			dat.entry(id).or_insert_with(|| loaded).clone()
+		})
+	}
+
+	async fn set_guild_data(&self, id: GuildId, data: Tables) {
+		crate::store::save_data::<Tables>(&id.0.to_string(), data.clone());
+
+		{
+			let mut dat = self.guild_data.write().await;
+			dat.insert(id, data);
+		}
+	}
+}
+
+async fn tables(ctx: &Context, msg: &Message) -> Result<Tables> {
+	let guild_id = msg.guild_id.ok_or_else(|| anyhow!("Must be in a guild"))?;
+	Persistent::from_context(ctx)
+		.await
+		.get_guild_data(guild_id)
+		.await
+}
+
+async fn update_tables(
+	ctx: &Context,
+	msg: &Message,
+	update: impl FnOnce(&mut Tables) -> Result<()>,
+) -> Result<()> {
+	let guild_id = msg.guild_id.ok_or_else(|| anyhow!("Must be in a guild"))?;
+	let persistent = Persistent::from_context(ctx).await;
+	let mut data = persistent.get_guild_data(guild_id).await?;
+	update(&mut data)?;
+	persistent.set_guild_data(guild_id, data).await;
+	Ok(())
+}
+
+// Random tables for each server, eg loot or random encounters
+#[group]
+#[prefix(table)]
+#[default_command(roll_table)]
+#[commands(add_table, roll_table, show_table, list_tables, remove_table)]
+struct Table;
+
+#[command("add")]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description(r#"Adds a random table for this server, or replaces one with the same name.
+
+Give the name then an entry on each line, like 1-3: 2d6 gold or 4: a magic sword. Entries count up from 1, and a line without a range takes the next number.
+Dice like 2d6 or 1d4+1 in an entry are rolled. [[gems]] rolls on the gems table, and [[4d6kh3]] rolls any expression.
+"#)]
+#[usage("loot\n1-3: 2d6 gold\n4: a magic sword\n5: [[gems]]")]
+#[bucket = "CONFIG_BUCKET"]
+async fn add_table(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let (name, lines) = args
+		.message()
+		.trim()
+		.split_once(char::is_whitespace)
+		.ok_or_else(|| anyhow!("Give the table's name then its entries, one on each line"))?;
+
+	update_tables(ctx, msg, |data| data.add(name, lines)).await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Added table {}", name.to_lowercase()))
+		.await?;
+	Ok(())
+}
+
+#[command("roll")]
+#[only_in(guilds)]
+#[description("Rolls on one of this server's random tables")]
+#[usage("loot")]
+async fn roll_table(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let data = tables(ctx, msg).await?;
+	let settings = super::config::settings(ctx, msg).await?;
+	let result = crate::rolls::roll_table(&data, args.message().trim(), &settings)?;
+	msg.channel_id.say(&ctx.http, result.to_markdown()).await?;
+	Ok(())
+}
+
+#[command("show")]
+#[only_in(guilds)]
+#[description("Shows the entries of one of this server's random tables")]
+#[usage("loot")]
+async fn show_table(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let name = args.message().trim();
+	let data = tables(ctx, msg).await?;
+	let table = data
+		.get(name)
+		.ok_or_else(|| anyhow!("No table named {}", name))?;
+	msg.channel_id
+		.say(&ctx.http, format!("{}:\n{}", name.to_lowercase(), table))
+		.await?;
+	Ok(())
+}
+
+#[command("list")]
+#[only_in(guilds)]
+#[description("Lists this server's random tables")]
+#[usage("")]
+async fn list_tables(ctx: &Context, msg: &Message) -> CommandResult {
+	use itertools::Itertools;
+
+	let data = tables(ctx, msg).await?;
+	let result = if data.tables.is_empty() {
+		"No tables. Add one with table add.".to_string()
+	} else {
+		data.tables
+			.iter()
+			.map(|(name, table)| format!("{} (d{})", name, table.size()))
+			.join("\n")
+	};
+	msg.channel_id.say(&ctx.http, result).await?;
+	Ok(())
+}
+
+#[command("remove")]
+#[only_in(guilds)]
+#[checks(ManageServer)]
+#[description("Removes a random table from this server")]
+#[usage("loot")]
+#[bucket = "CONFIG_BUCKET"]
+async fn remove_table(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+	let name = args.message().trim().to_lowercase();
+
+	update_tables(ctx, msg, |data| {
+		data.tables
+			.remove(&name)
+			.map(|_| ())
+			.ok_or_else(|| anyhow!("No table named {}", name))
+	})
+	.await?;
+	msg.channel_id
+		.say(&ctx.http, format!("Removed table {name}"))
+		.await?;
+	Ok(())
+}
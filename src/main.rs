@@ -41,6 +41,24 @@ impl TypeMapKey for RoleData {
 	type Value = Arc<commands::roles::Persistent>;
 }
 
+struct SettingsData;
+
+impl TypeMapKey for SettingsData {
+	type Value = Arc<commands::config::Persistent>;
+}
+
+struct TableData;
+
+impl TypeMapKey for TableData {
+	type Value = Arc<commands::tables::Persistent>;
+}
+
+struct DeckData;
+
+impl TypeMapKey for DeckData {
+	type Value = Arc<commands::deck::Persistent>;
+}
+
 struct Handler;
 
 #[async_trait]
@@ -122,6 +140,9 @@ async fn start() -> Result<()> {
 		let mut data = client.data.write().await;
 		data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
 		data.insert::<RoleData>(Arc::new(commands::roles::Persistent::default()));
+		data.insert::<SettingsData>(Arc::new(commands::config::Persistent::default()));
+		data.insert::<TableData>(Arc::new(commands::tables::Persistent::default()));
+		data.insert::<DeckData>(Arc::new(commands::deck::Persistent::default()));
 	}
 
 	{
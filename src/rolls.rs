@@ -1,196 +1,553 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, ensure, Result};
 use rand::Rng;
-use regex::{Captures, Regex, Replacer};
-use std::borrow::Cow;
+use serde::Serialize;
 
 pub type DiceInt = i32;
 
+mod check;
+mod deck;
+mod distribution;
+mod eval;
+mod moves;
+mod narrative;
 mod options;
+mod parser;
+mod random;
+mod render;
+mod savage;
+mod settings;
+mod tables;
 #[cfg(test)]
 mod test;
 
-use options::Options;
+pub use check::{Check, Degree};
+pub use deck::{Deck, DeckKind};
+pub use distribution::Distribution;
+pub use eval::{Rolled, Value};
+pub use moves::{Band, Move, MoveCrit, MoveRoll};
+pub use narrative::{NarrativeDie, NarrativeRoll, Symbols};
+pub use options::{
+	Compare, Condition, Count, Explode, Explosion, Fate, Keep, Options, Percentile, Reroll,
+};
+pub use parser::{BinOp, Function};
+pub use random::{Coin, Flips};
+pub use render::Style;
+pub use savage::SavageRoll;
+pub use settings::{Face, Settings};
+pub use tables::{Entry, Table, TableRoll, Tables};
 
 const MAX_ROLLED_DICE: DiceInt = 500;
 const MAX_DICE_SIDES: DiceInt = 10_000;
+// Limit on individual rolls for one expression, including rerolls, explosions and repeats
+const MAX_DICE_ROLLS: DiceInt = MAX_ROLLED_DICE * 100;
+// Same as the limit on roll_many, and shared by repeats inside repeats
+const MAX_REPEATS: usize = 100;
 
-lazy_static! {
-	static ref ROLL_REGEX: Regex =
-		Regex::new(r"(^|[+\- (])(\d+d[^+\-\*/ )]+)($|[$+\-\*/ )])").expect("Hardcoded regex");
+// The outcome of rolling an expression, keeping every dice rolled along the way
+#[derive(Debug, Serialize)]
+pub struct RollResult {
+	pub expression: String,
+	pub comment: Option<String>,
+	pub rolled: Rolled,
+	pub value: Value,
+	// whether any counted dice rolled a natural crit or fumble
+	pub crit: bool,
+	pub fumble: bool,
+	pub check: Option<Check>,
 }
 
-pub fn roll_expression_value(msg: &str) -> Result<DiceInt> {
+pub fn roll(msg: &str, settings: &Settings) -> Result<RollResult> {
+	roll_with_rng(msg, settings, &mut rand::thread_rng())
+}
+
+// Rolls each of several expressions separated by ; or , eg "1d20+7; 2d6+4"
+pub fn roll_all(msg: &str, settings: &Settings) -> Result<Vec<RollResult>> {
+	let parts: Vec<&str> = parser::split_expressions(msg)
+		.into_iter()
+		.filter(|part| !part.trim().is_empty())
+		.collect();
+	if parts.is_empty() {
+		return Ok(vec![roll(msg, settings)?]);
+	}
+	parts.iter().map(|part| roll(part, settings)).collect()
+}
+
+pub fn roll_expression_value(msg: &str, settings: &Settings) -> Result<DiceInt> {
 	use num_traits::cast::ToPrimitive;
-	let (_, _, vals) = roll_expressions(msg, &mut rand::thread_rng())?;
-	let evaled = meval::eval_str(&vals).map_err(|_e| anyhow!("Couldn't evaluate {}", vals))?;
-	Ok(evaled
+	roll(msg, settings)?
+		.value
+		.number()?
 		.to_i32()
-		.ok_or_else(|| anyhow!("Result out of range"))?)
+		.ok_or_else(|| anyhow!("Result out of range"))
 }
 
-pub fn roll_expression(msg: &str) -> Result<String> {
-	let (rolls, dice, vals) = roll_expressions(msg, &mut rand::thread_rng())?;
+// One line for each expression
+pub fn roll_expression(msg: &str, settings: &Settings) -> Result<String> {
+	use itertools::Itertools;
 
-	let single_simple_roll = rolls.len() == 1
-		&& rolls[0].options.explode.is_none()
-		&& rolls[0].options.number_of_dice == 1
-		&& vals.parse::<DiceInt>().is_ok();
+	Ok(roll_all(msg, settings)?
+		.iter()
+		.map(RollResult::to_markdown)
+		.join("\n"))
+}
 
-	Ok(if single_simple_roll {
-		format!("**{}**", vals)
-	} else {
-		let evaled = meval::eval_str(&vals).map_err(|_e| anyhow!("Couldn't evaluate {}", vals))?;
-		format!("{} => **{}**", dice, evaled)
-	})
+// A Savage Worlds trait roll eg "d8+1" or "d10 vs 6"
+pub fn savage_worlds(msg: &str, settings: &Settings) -> Result<SavageRoll> {
+	savage::trait_roll(msg, settings, &mut rand::thread_rng())
 }
 
-fn roll_expressions(msg: &str, rng: &mut impl Rng) -> Result<(Vec<DiceRoll>, String, String)> {
-	let mut rolls = vec![];
+// A pool of Genesys or Star Wars FFG symbol dice eg "2a1p1d1c"
+pub fn narrative(pool: &str) -> Result<NarrativeRoll> {
+	narrative::roll(pool, &mut rand::thread_rng())
+}
 
-	let result_rolled = {
-		let mut idx = 0;
-		let mut err = Ok(());
-		let result =
-			regex_replace_all_overlapping(&ROLL_REGEX, Cow::from(msg), |caps: &Captures| {
-				let roll = match DiceRoll::from_str(&caps[2], rng) {
-					Ok(roll) => roll,
-					Err(e) => {
-						err = Err(e);
-						return "".to_string();
-					}
-				};
-				let rep = format!("{}{}{}", &caps[1], roll.dice(), &caps[3]);
-				rolls.push(roll);
-				idx += 1;
-				rep
-			});
-		err?;
-		result
+// A move with bands for its outcomes eg "pbta +2", or just "+2" for the pbta move.
+// The first word is the move's name if this server has a move by that name.
+pub fn roll_move(msg: &str, settings: &Settings) -> Result<MoveRoll> {
+	let msg = msg.trim();
+	let (first, rest) = msg.split_once(char::is_whitespace).unwrap_or((msg, ""));
+	let (name, to_roll, argument) = match settings.find_move(first) {
+		Some(to_roll) => (first.to_lowercase(), to_roll, rest),
+		None => (
+			"pbta".to_string(),
+			settings
+				.find_move("pbta")
+				.ok_or_else(|| anyhow!("No pbta move"))?,
+			msg,
+		),
 	};
+	moves::roll(&name, &to_roll, argument, settings, &mut rand::thread_rng())
+}
 
-	let result_valued = {
-		let mut idx = 0;
-		let sums: Vec<DiceInt> = rolls
-			.iter()
-			.map(DiceRoll::val)
-			.collect::<Result<Vec<DiceInt>>>()?;
-		regex_replace_all_overlapping(&ROLL_REGEX, Cow::from(msg), |caps: &Captures| {
-			let rep = format!("{}{}{}", &caps[1], sums[idx], &caps[3]);
-			idx += 1;
-			rep
+// A result from one of a server's random tables, with any dice and nested tables in it rolled
+pub fn roll_table(tables: &Tables, name: &str, settings: &Settings) -> Result<TableRoll> {
+	tables.roll(name, settings, &mut rand::thread_rng())
+}
+
+// One of a list of items, which can be weighted like 3:sword
+pub fn choose(list: &str) -> Result<String> {
+	random::choose(list, &mut rand::thread_rng())
+}
+
+// A list of items in a random order
+pub fn shuffle(list: &str) -> Result<Vec<String>> {
+	random::shuffle(list, &mut rand::thread_rng())
+}
+
+pub fn flip(count: usize) -> Result<Flips> {
+	random::flip(count, &mut rand::thread_rng())
+}
+
+// Exact odds of each result of an expression, rather than a random sample
+pub fn expression_distribution(msg: &str, settings: &Settings) -> Result<Distribution> {
+	distribution::of_total(&parser::parse(msg)?, settings)
+}
+
+// How many times to repeat a roll, which must be a whole number up to MAX_REPEATS
+fn repeats(times: f64) -> Result<usize> {
+	use num_traits::cast::ToPrimitive;
+	times
+		.to_usize()
+		.filter(|n| times.fract() == 0.0 && (1..=MAX_REPEATS).contains(n))
+		.ok_or_else(|| {
+			anyhow!(
+				"Can repeat a roll between 1 and {} times. Got: {}",
+				MAX_REPEATS,
+				times
+			)
 		})
+}
+
+// Dice rolled and repeats made so far by one expression, so nested repeats
+// can't multiply the limits on them
+#[derive(Debug, Default)]
+pub struct Budget {
+	rolled: DiceInt,
+	repeats: usize,
+}
+
+impl Budget {
+	fn roll_dice(&mut self, count: DiceInt) -> Result<()> {
+		self.rolled += count;
+		ensure!(
+			self.rolled <= MAX_DICE_ROLLS,
+			"Stopped after rolling {} dice. Check your rerolls and explosions can end, and repeats don't roll too many dice.",
+			MAX_DICE_ROLLS
+		);
+		Ok(())
+	}
+
+	// How many times to repeat a roll, counting it against MAX_REPEATS
+	fn repeat(&mut self, times: f64) -> Result<usize> {
+		let times = repeats(times)?;
+		self.repeats += times;
+		ensure!(
+			self.repeats <= MAX_REPEATS,
+			"Can repeat rolls at most {} times in total, including repeats inside repeats",
+			MAX_REPEATS
+		);
+		Ok(times)
+	}
+}
+
+fn roll_with_rng(msg: &str, settings: &Settings, rng: &mut impl Rng) -> Result<RollResult> {
+	roll_statement(msg, parser::parse_statement(msg)?, settings, rng)
+}
+
+fn roll_statement(
+	msg: &str,
+	statement: parser::Statement,
+	settings: &Settings,
+	rng: &mut impl Rng,
+) -> Result<RollResult> {
+	let parser::Statement {
+		expr,
+		target,
+		comment,
+	} = statement;
+	let mut budget = Budget::default();
+	let rolled = eval::roll(&expr, settings, &mut budget, rng)?;
+	let value = rolled.value()?.round_down();
+	let check = match target {
+		Some(target) => Some(Check::new(
+			value.number()?,
+			target.compare,
+			eval::roll(&target.value, settings, &mut budget, rng)?,
+		)?),
+		None => None,
 	};
+	let (mut crit, mut fumble) = (false, false);
+	rolled.for_each_dice(&mut |dice| {
+		for die in dice.rolls.iter().filter(|it| !it.dropped) {
+			crit |= die.natural == Some(Natural::Crit);
+			fumble |= die.natural == Some(Natural::Fumble);
+		}
+	});
+	Ok(RollResult {
+		expression: msg.trim().to_string(),
+		comment,
+		rolled,
+		value,
+		crit,
+		fumble,
+		check,
+	})
+}
+
+// A single NdN roll eg 3d20 -> [1, 5, 20]
+#[derive(Eq, PartialEq, Debug, Serialize)]
+pub struct DiceRoll {
+	pub options: Options,
+	pub rolls: Vec<Die>,
+}
 
-	Ok((rolls, result_rolled, result_valued))
+// One rolled dice. Dropped dice were discarded by a keep/drop option.
+// Rerolled holds earlier values which a reroll option replaced, oldest first.
+// Exploded dice were added by, or compounded with, an explosion.
+// Symbol is the face's symbol for dice with symbol faces, eg hit.
+// Natural is set when the natural roll is in the term's crit or fumble range.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+pub struct Die {
+	pub value: DiceInt,
+	pub dropped: bool,
+	pub rerolled: Vec<DiceInt>,
+	pub exploded: bool,
+	pub symbol: Option<String>,
+	pub natural: Option<Natural>,
 }
 
-fn regex_replace_all_overlapping(
-	regex: &Regex,
-	mut msg: Cow<str>,
-	mut replacer: impl Replacer,
-) -> String {
-	loop {
-		match regex.replace(&msg, replacer.by_ref()) {
-			Cow::Borrowed(_) => break,
-			Cow::Owned(owned) => msg = Cow::from(owned),
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Natural {
+	Crit,
+	Fumble,
+}
+
+impl Die {
+	const fn new(value: DiceInt) -> Self {
+		Self {
+			value,
+			dropped: false,
+			rerolled: vec![],
+			exploded: false,
+			symbol: None,
+			natural: None,
 		}
 	}
-	msg.to_string()
 }
 
-// A single NdN roll eg 3d20 -> [1, 5, 20]
-#[derive(Eq, PartialEq, Debug)]
-struct DiceRoll {
-	options: Options,
-	rolls: Vec<DiceInt>,
+struct Roller<'a, R: Rng> {
+	options: &'a Options,
+	rng: &'a mut R,
+	budget: &'a mut Budget,
 }
 
-impl DiceRoll {
-	fn from_str(str: &str, rng: &mut impl Rng) -> Result<Self> {
-		let options = options::parse(str)?;
+impl<R: Rng> Roller<'_, R> {
+	// Rolls the index of a single side, counting it against MAX_DICE_ROLLS
+	fn face(&mut self) -> Result<usize> {
+		self.budget.roll_dice(1)?;
+		Ok(self.rng.gen_range(0..self.options.dice_sides.len()))
+	}
 
-		let dice_size_bound = options.dice_sides.len();
+	fn side(&mut self) -> Result<DiceInt> {
+		Ok(self.options.dice_sides[self.face()?])
+	}
 
-		let max_possible_roll = *options
-			.dice_sides
-			.iter()
-			.max()
-			.ok_or_else(|| anyhow!("Must have at least one dice side"))?;
+	// Rolls a new dice, applying the reroll option if there is one
+	fn die(&mut self) -> Result<Die> {
+		let options = self.options;
+		let sides = &options.dice_sides;
+		let mut face = self.face()?;
+		let mut rerolled = vec![];
+		if let Some(reroll) = &options.reroll {
+			while reroll.condition.matches(sides[face]) {
+				rerolled.push(sides[face]);
+				face = self.face()?;
+				if !reroll.until {
+					break;
+				}
+			}
+		}
+		let natural = sides[face];
+		Ok(Die {
+			rerolled,
+			symbol: options.symbols.get(face).cloned().flatten(),
+			natural: options.natural(natural),
+			..Die::new(natural)
+		})
+	}
+}
+
+impl DiceRoll {
+	fn from_str(
+		str: &str,
+		settings: &Settings,
+		budget: &mut Budget,
+		rng: &mut impl Rng,
+	) -> Result<Self> {
+		use std::convert::TryFrom;
+
+		let options = options::parse(str, settings)?;
+		if let Some(percentile) = &options.percentile {
+			let rolls = percentile_rolls(percentile, rng);
+			budget.roll_dice(DiceInt::try_from(rolls.len())?)?;
+			return Ok(Self { options, rolls });
+		}
 
+		let mut roller = Roller {
+			options: &options,
+			rng,
+			budget,
+		};
 		let mut rolls = vec![];
 		let mut dice_to_roll = options.number_of_dice;
+		let mut exploding = false;
+		// rolls made by explosions, counted against the explosion limit
+		let mut extra_rolls: DiceInt = 0;
+		let penetrating = matches!(
+			&options.explode,
+			Some(Explosion {
+				kind: Explode::Penetrating,
+				..
+			})
+		);
 		while dice_to_roll > 0 {
-			// dice which hit max value which need exploded
-			let mut maxed: DiceInt = 0;
+			// dice which need exploded into a new dice
+			let mut explosions: DiceInt = 0;
 			for _ in 0..dice_to_roll {
-				let mut current_roll = options.dice_sides[rng.gen_range(0..dice_size_bound)];
-				let mut total = current_roll;
-				if current_roll == max_possible_roll && options.explode != None {
-					maxed = maxed.checked_add(1).ok_or_else(|| {
-						anyhow!("Overflow due to overflow tracking exploded dice count.")
-					})?;
-					if options.explode == Some(options::Explode::Compounding) {
-						while current_roll == max_possible_roll {
-							current_roll = options.dice_sides[rng.gen_range(0..dice_size_bound)];
-							total = total.checked_add(current_roll).ok_or_else(|| {
-								anyhow!("Overflow due to overflow during compounded explode")
-							})?;
+				let mut die = roller.die()?;
+				let mut current_roll = die.value;
+				if exploding {
+					die.exploded = true;
+					if penetrating {
+						die.value -= 1;
+					}
+				}
+				if let Some(explosion) = &options.explode {
+					if explosion.condition.matches(current_roll) {
+						if explosion.kind == Explode::Compounding {
+							while explosion.condition.matches(current_roll)
+								&& explosion.allows(extra_rolls)
+							{
+								die.exploded = true;
+								extra_rolls += 1;
+								current_roll = roller.side()?;
+								die.value =
+									die.value.checked_add(current_roll).ok_or_else(|| {
+										anyhow!(
+											"Overflow due to overflow during compounded explode"
+										)
+									})?;
+							}
+						} else {
+							explosions += 1;
 						}
 					}
 				}
-				rolls.push(total);
+				rolls.push(die);
 			}
 
-			dice_to_roll = 0;
-			if options.explode == Some(options::Explode::Standard) {
-				dice_to_roll = maxed;
-			}
+			dice_to_roll = options.explode.as_ref().map_or(0, |explosion| {
+				explosion
+					.limit
+					.map_or(explosions, |limit| explosions.min(limit - extra_rolls))
+			});
+			extra_rolls += dice_to_roll;
+			exploding = true;
+		}
+
+		if let Some(keep) = &options.keep {
+			apply_keep(&mut rolls, keep);
 		}
 
 		Ok(Self { options, rolls })
 	}
 
-	const fn check_dice(&self, dice: DiceInt) -> bool {
-		if let Some(min) = self.options.min {
-			if dice <= min {
-				return false;
-			}
+	fn check_dice(&self, dice: &Die) -> bool {
+		!dice.dropped && self.options.passes_filters(dice.value)
+	}
+
+	fn val(&self) -> Result<DiceInt> {
+		if self.options.percentile.is_some() {
+			let total = self
+				.rolls
+				.iter()
+				.filter(|it| !it.dropped)
+				.map(|it| it.value)
+				.sum();
+			return Ok(percentile_total(total));
 		}
-		if let Some(max) = self.options.max {
-			if dice >= max {
-				return false;
-			}
+		if let Some(count) = &self.options.count {
+			let (successes, failures) = self.successes(count);
+			return Ok(successes - failures);
 		}
 
-		true
+		let mut sum: DiceInt = 0;
+		for roll in &self.rolls {
+			if self.check_dice(roll) {
+				sum = sum
+					.checked_add(roll.value)
+					.ok_or_else(|| anyhow!("Overflow summing dice values"))?;
+			}
+		}
+		Ok(sum)
 	}
 
-	fn dice(&self) -> String {
-		format!(
-			"[{}]",
+	// (successes, failures) for a roll which counts successes
+	fn successes(&self, count: &options::Count) -> (DiceInt, DiceInt) {
+		// can't overflow as there are at most MAX_DICE_ROLLS dice
+		let successes = self
+			.rolls
+			.iter()
+			.filter(|it| self.check_dice(it))
+			.fold(0, |n, _| n + 1);
+		let failures = count.failure.as_ref().map_or(0, |failure| {
 			self.rolls
 				.iter()
-				.map(|it| {
-					(if self.check_dice(*it) {
-						it.to_string()
-					} else {
-						format!("~~{}~~", it)
-					}) + ", "
+				.filter(|it| !it.dropped && failure.matches(it.value))
+				.fold(0, |n, _| n + 1)
+		});
+
+		(successes, failures)
+	}
+
+	// eg "3 successes", or "botch" when there were failures but no successes
+	fn successes_summary(&self) -> Option<String> {
+		let (successes, failures) = self.successes(self.options.count.as_ref()?);
+
+		Some(if successes == 0 && failures > 0 {
+			"botch".to_string()
+		} else {
+			let net = successes - failures;
+			format!("{} success{}", net, if net == 1 { "" } else { "es" })
+		})
+	}
+
+	// Tallies the symbols of counted dice, eg "2 hit, 1 crit", in the order the faces list them
+	fn symbols_summary(&self) -> Option<String> {
+		use itertools::Itertools;
+
+		let counted: Vec<&str> = self
+			.rolls
+			.iter()
+			.filter(|it| self.check_dice(it))
+			.filter_map(|it| it.symbol.as_deref())
+			.collect();
+		if counted.is_empty() {
+			return None;
+		}
+
+		Some(
+			self.options
+				.symbols
+				.iter()
+				.flatten()
+				.unique()
+				.filter_map(|symbol| {
+					let count = counted.iter().filter(|it| **it == symbol).count();
+					(count > 0).then(|| format!("{count} {symbol}"))
 				})
-				.collect::<String>()
-				.trim_end_matches(", ")
+				.join(", "),
 		)
 	}
+}
 
-	fn val(&self) -> Result<DiceInt> {
-		let mut sum: DiceInt = 0;
-		for roll in &self.rolls {
-			if self.check_dice(*roll) {
-				sum = sum
-					.checked_add(*roll)
-					.ok_or_else(|| anyhow!("Overflow summing dice values"))?;
-			}
+// Rolls the tens dice, then the units dice last. Every tens dice but the chosen one is dropped,
+// bonus dice keeping the lowest result and penalty dice the highest.
+fn percentile_rolls(percentile: &options::Percentile, rng: &mut impl Rng) -> Vec<Die> {
+	let units = rng.gen_range(0..10);
+	let extra = percentile.bonus - percentile.penalty;
+	let mut tens: Vec<Die> = (0..=extra.abs())
+		.map(|_| Die::new(rng.gen_range(0..10) * 10))
+		.collect();
+
+	let totals = tens.iter().map(|die| percentile_total(die.value + units));
+	let chosen = if extra > 0 {
+		totals.enumerate().min_by_key(|(_, total)| *total)
+	} else {
+		totals.enumerate().max_by_key(|(_, total)| *total)
+	}
+	.map_or(0, |(idx, _)| idx);
+	for (idx, die) in tens.iter_mut().enumerate() {
+		die.dropped = idx != chosen;
+	}
+
+	tens.push(Die::new(units));
+	tens
+}
+
+// 00 and 0 is 100 rather than 0
+const fn percentile_total(total: DiceInt) -> DiceInt {
+	if total == 0 {
+		100
+	} else {
+		total
+	}
+}
+
+// Marks the dice which a keep/drop option discards as dropped.
+// Ties are broken in favour of keeping the earlier dice.
+fn apply_keep(rolls: &mut [Die], keep: &options::Keep) {
+	use options::Keep;
+	use std::convert::TryFrom;
+
+	let mut order: Vec<usize> = (0..rolls.len()).collect();
+	match keep {
+		Keep::Highest(_) | Keep::DropLowest(_) => {
+			order.sort_by_key(|idx| std::cmp::Reverse(rolls[*idx].value));
 		}
-		Ok(sum)
+		Keep::Lowest(_) | Keep::DropHighest(_) => order.sort_by_key(|idx| rolls[*idx].value),
+	}
+
+	let count = rolls.len();
+	let kept = match *keep {
+		Keep::Highest(n) | Keep::Lowest(n) => usize::try_from(n).unwrap_or(0).min(count),
+		Keep::DropHighest(n) | Keep::DropLowest(n) => {
+			count.saturating_sub(usize::try_from(n).unwrap_or(0))
+		}
+	};
+
+	for idx in &order[kept..] {
+		rolls[*idx].dropped = true;
 	}
 }
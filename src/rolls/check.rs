@@ -0,0 +1,67 @@
+use super::{Compare, Rolled};
+use anyhow::Result;
+use serde::Serialize;
+
+// How a roll did against a target eg 1d20+5 vs 15
+#[derive(Debug, Serialize)]
+pub struct Check {
+	pub compare: Compare,
+	pub target: Rolled,
+	pub target_value: f64,
+	pub success: bool,
+	pub margin: f64, // how far the roll passed or missed by
+	pub degree: Option<Degree>,
+}
+
+// Call of Cthulhu style degrees of success, for rolling under a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Degree {
+	Regular,
+	Hard,    // at most half the target
+	Extreme, // at most a fifth of the target
+}
+
+impl Check {
+	pub fn new(value: f64, compare: Compare, target: Rolled) -> Result<Self> {
+		let target_value = target.value()?.number()?;
+		let success = match compare {
+			Compare::Equal => (value - target_value).abs() < f64::EPSILON,
+			Compare::NotEqual => (value - target_value).abs() >= f64::EPSILON,
+			Compare::LessThan => value < target_value,
+			Compare::LessOrEqual => value <= target_value,
+			Compare::GreaterThan => value > target_value,
+			Compare::GreaterOrEqual => value >= target_value,
+		};
+		let rolling_under = matches!(compare, Compare::LessThan | Compare::LessOrEqual);
+		let degree = (success && rolling_under).then(|| {
+			if value <= (target_value / 5.0).floor() {
+				Degree::Extreme
+			} else if value <= (target_value / 2.0).floor() {
+				Degree::Hard
+			} else {
+				Degree::Regular
+			}
+		});
+
+		Ok(Self {
+			compare,
+			target,
+			target_value,
+			success,
+			margin: (value - target_value).abs(),
+			degree,
+		})
+	}
+
+	// eg "hard success" or "failure"
+	#[must_use]
+	pub const fn verdict(&self) -> &'static str {
+		match (self.success, self.degree) {
+			(false, _) => "failure",
+			(true, Some(Degree::Extreme)) => "extreme success",
+			(true, Some(Degree::Hard)) => "hard success",
+			(true, _) => "success",
+		}
+	}
+}
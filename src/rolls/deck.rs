@@ -0,0 +1,192 @@
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// Most cards a custom deck can have, and that can be drawn or peeked at once
+const MAX_DECK_SIZE: usize = 500;
+const MAX_DRAW: usize = 54;
+
+const RANKS: [&str; 13] = [
+	"A", "2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K",
+];
+const SUITS: [&str; 4] = ["♠", "♥", "♦", "♣"];
+const MAJOR_ARCANA: [&str; 22] = [
+	"The Fool",
+	"The Magician",
+	"The High Priestess",
+	"The Empress",
+	"The Emperor",
+	"The Hierophant",
+	"The Lovers",
+	"The Chariot",
+	"Strength",
+	"The Hermit",
+	"Wheel of Fortune",
+	"Justice",
+	"The Hanged Man",
+	"Death",
+	"Temperance",
+	"The Devil",
+	"The Tower",
+	"The Star",
+	"The Moon",
+	"The Sun",
+	"Judgement",
+	"The World",
+];
+const TAROT_RANKS: [&str; 14] = [
+	"Ace", "Two", "Three", "Four", "Five", "Six", "Seven", "Eight", "Nine", "Ten", "Page",
+	"Knight", "Queen", "King",
+];
+const TAROT_SUITS: [&str; 4] = ["Wands", "Cups", "Swords", "Pentacles"];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeckKind {
+	#[default]
+	Standard, // 52 cards
+	Jokers, // 52 cards and a red and black joker, eg for Savage Worlds initiative
+	Tarot,  // 78 cards
+	Custom(Vec<String>),
+}
+
+// A channel's deck. Cards are drawn from the front of the pile and
+// stay out of it until they're returned or the deck is shuffled.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Deck {
+	pub kind: DeckKind,
+	pub pile: Vec<String>,
+	pub drawn: Vec<String>,
+}
+
+impl DeckKind {
+	// standard, jokers, tarot, or comma separated cards for a custom deck
+	pub fn parse(text: &str) -> Result<Self> {
+		Ok(match text.trim().to_lowercase().as_str() {
+			"standard" | "52" => Self::Standard,
+			"jokers" | "54" => Self::Jokers,
+			"tarot" => Self::Tarot,
+			_ => {
+				let cards: Vec<String> = text
+					.split(',')
+					.map(|card| card.trim().to_string())
+					.collect();
+				ensure!(
+					cards.len() > 1 && cards.iter().all(|card| !card.is_empty()),
+					"Use standard, jokers, tarot, or a custom deck of cards separated by commas, eg Deck, of, Many, Things"
+				);
+				ensure!(
+					cards.len() <= MAX_DECK_SIZE,
+					"Decks can have at most {} cards",
+					MAX_DECK_SIZE
+				);
+				Self::Custom(cards)
+			}
+		})
+	}
+
+	#[must_use]
+	pub fn cards(&self) -> Vec<String> {
+		let standard = || {
+			SUITS
+				.iter()
+				.flat_map(|suit| RANKS.iter().map(move |rank| format!("{rank}{suit}")))
+		};
+		match self {
+			Self::Standard => standard().collect(),
+			Self::Jokers => standard()
+				.chain(vec!["Red Joker".to_string(), "Black Joker".to_string()])
+				.collect(),
+			Self::Tarot => MAJOR_ARCANA
+				.iter()
+				.map(|card| (*card).to_string())
+				.chain(TAROT_SUITS.iter().flat_map(|suit| {
+					TAROT_RANKS
+						.iter()
+						.map(move |rank| format!("{rank} of {suit}"))
+				}))
+				.collect(),
+			Self::Custom(cards) => cards.clone(),
+		}
+	}
+}
+
+impl Deck {
+	// Puts every card back and shuffles them, switching to another kind of deck if given one
+	pub fn shuffle(&mut self, kind: Option<DeckKind>, rng: &mut impl Rng) {
+		if let Some(kind) = kind {
+			self.kind = kind;
+		}
+		self.pile = self.kind.cards();
+		self.pile.shuffle(rng);
+		self.drawn.clear();
+	}
+
+	pub fn draw(&mut self, count: usize, rng: &mut impl Rng) -> Result<Vec<String>> {
+		// a channel's first deck hasn't been shuffled yet
+		if self.pile.is_empty() && self.drawn.is_empty() {
+			self.shuffle(None, rng);
+		}
+		self.check_count(count)?;
+		let cards: Vec<String> = self.pile.drain(..count).collect();
+		self.drawn.extend(cards.iter().cloned());
+		Ok(cards)
+	}
+
+	// The next cards to be drawn, without drawing them
+	pub fn peek(&mut self, count: usize, rng: &mut impl Rng) -> Result<&[String]> {
+		if self.pile.is_empty() && self.drawn.is_empty() {
+			self.shuffle(None, rng);
+		}
+		self.check_count(count)?;
+		Ok(&self.pile[..count])
+	}
+
+	// Shuffles drawn cards back into the pile, either the named ones or all of them.
+	// Returns how many cards went back.
+	pub fn return_cards(&mut self, cards: &[&str], rng: &mut impl Rng) -> Result<usize> {
+		let returned: Vec<String> = if cards.is_empty() {
+			std::mem::take(&mut self.drawn)
+		} else {
+			cards
+				.iter()
+				.map(|card| {
+					let position = self
+						.drawn
+						.iter()
+						.position(|drawn| drawn.eq_ignore_ascii_case(card.trim()));
+					match position {
+						Some(position) => Ok(self.drawn.remove(position)),
+						None => Err(anyhow!("{} hasn't been drawn", card.trim())),
+					}
+				})
+				.collect::<Result<_>>()?
+		};
+		let count = returned.len();
+		self.pile.extend(returned);
+		self.pile.shuffle(rng);
+		Ok(count)
+	}
+
+	fn check_count(&self, count: usize) -> Result<()> {
+		ensure!(
+			(1..=MAX_DRAW).contains(&count),
+			"Can draw between 1 and {} cards at once",
+			MAX_DRAW
+		);
+		ensure!(
+			count <= self.pile.len(),
+			"Only {} cards left. Return them with deck return or start again with deck shuffle",
+			self.pile.len()
+		);
+		Ok(())
+	}
+
+	// eg "A♠, 10♥ (50 left)"
+	#[must_use]
+	pub fn describe(&self, cards: &[String]) -> String {
+		format!("{} ({} left)", cards.iter().join(", "), self.pile.len())
+	}
+}
@@ -0,0 +1,674 @@
+use super::options::{Condition, Explode, Explosion, Keep, Options, Percentile};
+use super::parser::{Expr, Function};
+use super::{DiceInt, Settings};
+use anyhow::{anyhow, ensure, Result};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+// Limit on pairs of outcomes considered for any one step of the calculation
+const MAX_COMBINATIONS: usize = 4_000_000;
+// Explosions are followed until the chance of exploding again drops below this
+const EXPLOSION_CUTOFF: f64 = 1e-9;
+const MAX_EXPLOSION_DEPTH: usize = 100;
+// Limit on the different dice terms a nested dice like (1d4)d6 can work out to
+const MAX_NESTED_TERMS: usize = 1_000;
+
+// Probability of each value of a dice term
+type Weights = BTreeMap<DiceInt, f64>;
+
+// f64 with a total order so it can be used as a map key
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Outcome(f64);
+
+impl Eq for Outcome {}
+
+impl Ord for Outcome {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.0.total_cmp(&other.0)
+	}
+}
+
+impl PartialOrd for Outcome {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+// The exact probability of each result of an expression
+#[derive(Debug, Clone)]
+pub struct Distribution {
+	outcomes: BTreeMap<Outcome, f64>,
+}
+
+impl Distribution {
+	fn from_outcomes(outcomes: impl IntoIterator<Item = (f64, f64)>) -> Self {
+		let mut result = BTreeMap::new();
+		for (value, probability) in outcomes {
+			// adding 0.0 turns -0.0 into 0.0 so they're the same outcome
+			*result.entry(Outcome(value + 0.0)).or_insert(0.0) += probability;
+		}
+		Self { outcomes: result }
+	}
+
+	fn constant(value: f64) -> Self {
+		Self::from_outcomes(vec![(value, 1.0)])
+	}
+
+	fn map(&self, f: impl Fn(f64) -> Result<f64>) -> Result<Self> {
+		let mut outcomes = vec![];
+		for (value, probability) in self.outcomes() {
+			outcomes.push((f(value)?, probability));
+		}
+		Ok(Self::from_outcomes(outcomes))
+	}
+
+	fn combine(&self, other: &Self, f: impl Fn(f64, f64) -> Result<f64>) -> Result<Self> {
+		check_combinations(self.possible_results(), other.possible_results())?;
+		let mut outcomes = vec![];
+		for (lhs, lhs_probability) in self.outcomes() {
+			for (rhs, rhs_probability) in other.outcomes() {
+				outcomes.push((f(lhs, rhs)?, lhs_probability * rhs_probability));
+			}
+		}
+		Ok(Self::from_outcomes(outcomes))
+	}
+
+	// (value, probability) in increasing order of value
+	pub fn outcomes(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+		self.outcomes
+			.iter()
+			.map(|(value, probability)| (value.0, *probability))
+	}
+
+	#[must_use]
+	pub fn possible_results(&self) -> usize {
+		self.outcomes.len()
+	}
+
+	#[must_use]
+	pub fn mean(&self) -> f64 {
+		self.outcomes()
+			.map(|(value, probability)| value * probability)
+			.sum()
+	}
+
+	#[must_use]
+	pub fn std_dev(&self) -> f64 {
+		let mean = self.mean();
+		self.outcomes()
+			.map(|(value, probability)| (value - mean).powi(2) * probability)
+			.sum::<f64>()
+			.sqrt()
+	}
+
+	#[must_use]
+	pub fn min(&self) -> f64 {
+		self.outcomes().next().map_or(0.0, |(value, _)| value)
+	}
+
+	#[must_use]
+	pub fn max(&self) -> f64 {
+		self.outcomes().last().map_or(0.0, |(value, _)| value)
+	}
+
+	// Smallest value which at least the given fraction of results are less than or equal to
+	#[must_use]
+	pub fn percentile(&self, fraction: f64) -> f64 {
+		let mut cumulative = 0.0;
+		for (value, probability) in self.outcomes() {
+			cumulative += probability;
+			// allow for rounding errors building up in the sum
+			if cumulative >= fraction - 1e-12 {
+				return value;
+			}
+		}
+		self.max()
+	}
+
+	// P(result >= value)
+	#[must_use]
+	pub fn at_least(&self, value: f64) -> f64 {
+		self.outcomes
+			.range(Outcome(value)..)
+			.map(|(_, probability)| probability)
+			.sum()
+	}
+}
+
+// Odds of the total of an expression, which is rounded down like a roll's
+pub fn of_total(expr: &Expr, settings: &Settings) -> Result<Distribution> {
+	of_expr(expr, settings)?.map(|value| Ok(super::eval::whole_number(value)))
+}
+
+pub fn of_expr(expr: &Expr, settings: &Settings) -> Result<Distribution> {
+	match expr {
+		Expr::Number(n) => Ok(Distribution::constant(*n)),
+		Expr::Dice(dice) => {
+			let weights = of_dice(&super::options::parse(dice, settings)?)?;
+			Ok(Distribution::from_outcomes(weights.into_iter().map(
+				|(value, probability)| (f64::from(value), probability),
+			)))
+		}
+		Expr::Neg(inner) => of_expr(inner, settings)?.map(|value| Ok(-value)),
+		Expr::Group(inner) | Expr::Labeled(inner, _) => of_expr(inner, settings),
+		Expr::Binary(op, lhs, rhs) => {
+			of_expr(lhs, settings)?.combine(&of_expr(rhs, settings)?, |lhs, rhs| op.apply(lhs, rhs))
+		}
+		Expr::NestedDice(nested) => {
+			let count = of_expr(&nested.count, settings)?;
+			let sides: Vec<(Option<f64>, f64)> = match &nested.sides {
+				Some(sides) => of_expr(sides, settings)?
+					.outcomes()
+					.map(|(value, probability)| (Some(value), probability))
+					.collect(),
+				None => vec![(None, 1.0)],
+			};
+			ensure!(
+				count.possible_results().saturating_mul(sides.len()) <= MAX_NESTED_TERMS,
+				"Too many possible dice counts and sides to calculate exact odds"
+			);
+
+			let mut outcomes = vec![];
+			for (count, count_probability) in count.outcomes() {
+				for (sides, sides_probability) in &sides {
+					let options = super::options::parse(&nested.term(count, *sides)?, settings)?;
+					for (value, probability) in of_dice(&options)? {
+						outcomes.push((
+							f64::from(value),
+							probability * count_probability * sides_probability,
+						));
+					}
+				}
+			}
+			Ok(Distribution::from_outcomes(outcomes))
+		}
+		Expr::Call(function, args) => {
+			ensure!(
+				*function != Function::Sort,
+				"Can't calculate odds for sort, it gives a list"
+			);
+			let mut items = vec![];
+			for arg in args {
+				items.extend(of_items(arg, settings)?);
+			}
+			let mut items = items.into_iter();
+			let first = items
+				.next()
+				.ok_or_else(|| anyhow!("{} needs an argument", function.name()))?;
+			if items.len() > 0 {
+				// only min, max and sum take more than one argument, and they can be applied pairwise
+				ensure!(
+					matches!(function, Function::Min | Function::Max | Function::Sum),
+					"Can't calculate odds for {} with several arguments",
+					function.name()
+				);
+				items.try_fold(first, |result, item| {
+					result.combine(&item, |lhs, rhs| function.apply(&[lhs, rhs]))
+				})
+			} else {
+				first.map(|value| function.apply(&[value]))
+			}
+		}
+		Expr::Repeat(..) => Err(anyhow!(
+			"Can't calculate odds for a list of results. Combine it with sum, min or max."
+		)),
+	}
+}
+
+// Each value an argument to a function stands for, so repeats give several
+fn of_items(expr: &Expr, settings: &Settings) -> Result<Vec<Distribution>> {
+	let (expr, count) = match expr {
+		Expr::Repeat(expr, count) => (expr, of_expr(count, settings)?),
+		_ => return Ok(vec![of_expr(expr, settings)?]),
+	};
+	ensure!(
+		count.possible_results() == 1,
+		"Can't calculate odds for a random number of repeats"
+	);
+	let times = super::repeats(count.min())?;
+	Ok(vec![of_expr(expr, settings)?; times])
+}
+
+fn of_dice(options: &Options) -> Result<Weights> {
+	if let Some(percentile) = &options.percentile {
+		return percentile_weights(percentile);
+	}
+	let sides = side_weights(options)?;
+	let faces = after_reroll(options, &sides);
+
+	if let Some(explosion) = &options.explode {
+		// the limit is shared by every dice in the term, so they aren't independent
+		if let Some(limit) = explosion.limit.filter(|_| options.number_of_dice > 1) {
+			ensure!(
+				options.keep.is_none(),
+				"Can't calculate exact odds for keeping or dropping dice with limited explosions"
+			);
+			return shared_limit(options, &sides, &faces, explosion, limit);
+		}
+	}
+
+	match (&options.explode, &options.keep) {
+		(Some(explosion), keep) if explosion.kind != Explode::Compounding => {
+			ensure!(
+				keep.is_none(),
+				"Can't calculate exact odds for keeping or dropping dice with standard explosions"
+			);
+			let chain = exploding_chain(options, &faces, explosion)?;
+			convolve_n(&chain, options.number_of_dice)
+		}
+		(explode, keep) => {
+			let values = match explode {
+				Some(explosion) => compounded(&sides, &faces, explosion)?,
+				None => faces,
+			};
+			if let Some(keep) = keep {
+				kept(options, &values, keep)
+			} else {
+				let contributions = map_weights(&values, |value| Ok(contribution(options, value)))?;
+				convolve_n(&contributions, options.number_of_dice)
+			}
+		}
+	}
+}
+
+// For each units dice the chosen tens dice is the best or worst of several, so with bonus dice
+// P(result = v) = P(every tens dice gives >= v) - P(every tens dice gives > v), and the reverse for penalty dice
+fn percentile_weights(percentile: &Percentile) -> Result<Weights> {
+	let extra = percentile.bonus - percentile.penalty;
+	let tens_dice = extra.abs() + 1;
+	let mut weights = Weights::new();
+	for units in 0..10 {
+		let totals: Vec<DiceInt> = (0..10)
+			.map(|tens| match tens * 10 + units {
+				0 => 100,
+				total => total,
+			})
+			.collect();
+		// chance every tens dice gives a total passing f
+		let all = |f: &dyn Fn(DiceInt) -> bool| -> Result<f64> {
+			Ok((to_f64(totals.iter().filter(|it| f(**it)).count())? / 10.0).powi(tens_dice))
+		};
+		for &total in &totals {
+			let probability = if extra > 0 {
+				all(&|it| it >= total)? - all(&|it| it > total)?
+			} else {
+				all(&|it| it <= total)? - all(&|it| it < total)?
+			};
+			*weights.entry(total).or_insert(0.0) += probability / 10.0;
+		}
+	}
+	Ok(weights)
+}
+
+fn side_weights(options: &Options) -> Result<Weights> {
+	let each = 1.0 / to_f64(options.dice_sides.len())?;
+	let mut weights = Weights::new();
+	for side in &options.dice_sides {
+		*weights.entry(*side).or_insert(0.0) += each;
+	}
+	Ok(weights)
+}
+
+// The value of a newly rolled dice once any reroll option has been applied
+fn after_reroll(options: &Options, sides: &Weights) -> Weights {
+	let Some(reroll) = &options.reroll else {
+		return sides.clone();
+	};
+
+	let rerolled: f64 = sides
+		.iter()
+		.filter(|(value, _)| reroll.condition.matches(**value))
+		.map(|(_, probability)| probability)
+		.sum();
+
+	sides
+		.iter()
+		.map(|(value, probability)| {
+			let kept = if reroll.condition.matches(*value) {
+				0.0
+			} else {
+				*probability
+			};
+			let probability = if reroll.until {
+				// rerolling until it doesn't match is the same as never rolling a matching value
+				kept / (1.0 - rerolled)
+			} else {
+				kept + rerolled * probability
+			};
+			(*value, probability)
+		})
+		.collect()
+}
+
+// How much a single dice adds to the total, after filtering or counting successes
+fn contribution(options: &Options, value: DiceInt) -> DiceInt {
+	let passes = options.passes_filters(value);
+	match &options.count {
+		Some(count) => {
+			let failed = count
+				.failure
+				.as_ref()
+				.is_some_and(|failure| failure.matches(value));
+			DiceInt::from(passes) - DiceInt::from(failed)
+		}
+		None if passes => value,
+		None => 0,
+	}
+}
+
+// Number of explosions to follow before treating the chain as finished
+fn explosion_depth(explode_probability: f64, limit: Option<DiceInt>) -> Result<usize> {
+	use std::convert::TryFrom;
+
+	let limit = limit.map(usize::try_from).transpose()?;
+	ensure!(
+		explode_probability < 1.0 || limit.is_some(),
+		"Can't calculate odds for dice which always explode"
+	);
+	let mut depth = 1;
+	let mut remaining = explode_probability;
+	while remaining > EXPLOSION_CUTOFF && depth < MAX_EXPLOSION_DEPTH {
+		remaining *= explode_probability;
+		depth += 1;
+	}
+	Ok(limit.map_or(depth, |limit| depth.min(limit)))
+}
+
+fn explode_probability(weights: &Weights, condition: &Condition) -> f64 {
+	weights
+		.iter()
+		.filter(|(value, _)| condition.matches(**value))
+		.map(|(_, probability)| probability)
+		.sum()
+}
+
+// Total contribution of a dice and every dice it explodes into.
+// Penetrating explosions take 1 from every dice but the first.
+fn exploding_chain(options: &Options, faces: &Weights, explosion: &Explosion) -> Result<Weights> {
+	let depth = explosion_depth(
+		explode_probability(faces, &explosion.condition),
+		explosion.limit,
+	)?;
+	let penalty = DiceInt::from(explosion.kind == Explode::Penetrating);
+	let penetrated = |value: DiceInt, penalty: DiceInt| {
+		value
+			.checked_sub(penalty)
+			.map(|value| contribution(options, value))
+			.ok_or_else(|| anyhow!("Overflow calculating penetrating dice"))
+	};
+
+	// built from the last explosion followed back to the first dice
+	let mut chain = map_weights(faces, |value| {
+		penetrated(value, if depth == 0 { 0 } else { penalty })
+	})?;
+	for level in 1..=depth {
+		let penalty = if level == depth { 0 } else { penalty };
+		let mut next = Weights::new();
+		for (value, probability) in faces {
+			let own = penetrated(*value, penalty)?;
+			if explosion.condition.matches(*value) {
+				for (rest, rest_probability) in &chain {
+					let total = own
+						.checked_add(*rest)
+						.ok_or_else(|| anyhow!("Overflow calculating exploded dice"))?;
+					*next.entry(total).or_insert(0.0) += probability * rest_probability;
+				}
+			} else {
+				*next.entry(own).or_insert(0.0) += probability;
+			}
+		}
+		chain = next;
+	}
+
+	Ok(chain)
+}
+
+// Total of a term whose explosions share one limit, eg 5d6!l3. Each dice can only explode
+// as many times as the dice before it left, so this works through them one at a time
+// keeping the chance of each (explosions left, total) pair. Rolling every dice before
+// its explosions, as rolling does, gives the same odds.
+fn shared_limit(
+	options: &Options,
+	sides: &Weights,
+	faces: &Weights,
+	explosion: &Explosion,
+	limit: DiceInt,
+) -> Result<Weights> {
+	let compounding = explosion.kind == Explode::Compounding;
+	let penalty = DiceInt::from(explosion.kind == Explode::Penetrating);
+	let add = |lhs: DiceInt, rhs: DiceInt| {
+		lhs.checked_add(rhs)
+			.ok_or_else(|| anyhow!("Overflow calculating exploded dice"))
+	};
+
+	let mut totals: BTreeMap<(DiceInt, DiceInt), f64> =
+		std::iter::once(((limit, 0), 1.0)).collect();
+	let mut combinations: usize = 0;
+	for _ in 0..options.number_of_dice {
+		let mut finished = BTreeMap::new();
+		// (explosions left, total, value of a compounding dice so far) while the dice explodes
+		let mut rolling: BTreeMap<(DiceInt, DiceInt, DiceInt), f64> = totals
+			.into_iter()
+			.map(|((left, total), probability)| ((left, total, 0), probability))
+			.collect();
+		let mut first = true;
+		while !rolling.is_empty() {
+			// only the first roll of a compounding dice can be rerolled
+			let rolls = if compounding && !first { sides } else { faces };
+			// counted across every dice, as each one's explosions are worked out in turn
+			combinations = combinations.saturating_add(rolling.len().saturating_mul(rolls.len()));
+			check_combinations(combinations, 1)?;
+			// explosions stop being followed once they're too unlikely, like exploding_chain
+			let can_explode = rolling.values().sum::<f64>() > EXPLOSION_CUTOFF;
+			let mut next = BTreeMap::new();
+			for (&(left, total, value), &probability) in &rolling {
+				for (&roll, &roll_probability) in rolls {
+					let probability = probability * roll_probability;
+					let explodes = can_explode && left > 0 && explosion.condition.matches(roll);
+					let (total, value) = if compounding {
+						(total, add(value, roll)?)
+					} else {
+						let own = if first { roll } else { roll - penalty };
+						(add(total, contribution(options, own))?, 0)
+					};
+					if explodes {
+						*next.entry((left - 1, total, value)).or_insert(0.0) += probability;
+					} else {
+						let total = if compounding {
+							add(total, contribution(options, value))?
+						} else {
+							total
+						};
+						*finished.entry((left, total)).or_insert(0.0) += probability;
+					}
+				}
+			}
+			rolling = next;
+			first = false;
+		}
+		totals = finished;
+	}
+
+	let mut result = Weights::new();
+	for ((_, total), probability) in totals {
+		*result.entry(total).or_insert(0.0) += probability;
+	}
+	Ok(result)
+}
+
+// Value of a compounding dice. Only the first roll can be rerolled.
+fn compounded(sides: &Weights, faces: &Weights, explosion: &Explosion) -> Result<Weights> {
+	let condition = &explosion.condition;
+	let depth = explosion_depth(explode_probability(sides, condition), explosion.limit)?;
+	if depth == 0 {
+		return Ok(faces.clone());
+	}
+
+	let mut extra = sides.clone();
+	for _ in 1..depth {
+		extra = explode_once(sides, &extra, condition)?;
+	}
+	explode_once(faces, &extra, condition)
+}
+
+// Rolls from first, adding a roll from then when it explodes
+fn explode_once(first: &Weights, then: &Weights, condition: &Condition) -> Result<Weights> {
+	let mut result = Weights::new();
+	for (value, probability) in first {
+		if condition.matches(*value) {
+			for (then_value, then_probability) in then {
+				let total = value
+					.checked_add(*then_value)
+					.ok_or_else(|| anyhow!("Overflow calculating compounded dice"))?;
+				*result.entry(total).or_insert(0.0) += probability * then_probability;
+			}
+		} else {
+			*result.entry(*value).or_insert(0.0) += probability;
+		}
+	}
+	Ok(result)
+}
+
+// Total of the dice kept by a keep or drop option.
+// Works through the possible values from most to least preferred, choosing how many dice show each.
+fn kept(options: &Options, values: &Weights, keep: &Keep) -> Result<Weights> {
+	use std::convert::TryFrom;
+
+	let count = usize::try_from(options.number_of_dice)?;
+	let (to_keep, highest) = match *keep {
+		Keep::Highest(n) => (usize::try_from(n)?.min(count), true),
+		Keep::Lowest(n) => (usize::try_from(n)?.min(count), false),
+		Keep::DropHighest(n) => (count.saturating_sub(usize::try_from(n)?), false),
+		Keep::DropLowest(n) => (count.saturating_sub(usize::try_from(n)?), true),
+	};
+
+	let mut order: Vec<(DiceInt, f64)> = values
+		.iter()
+		.map(|(value, probability)| (*value, *probability))
+		.collect();
+	if highest {
+		order.reverse();
+	}
+
+	// (dice assigned a value, dice kept) -> total of kept dice
+	let mut states: HashMap<(usize, usize), Weights> = HashMap::new();
+	states.insert((0, 0), std::iter::once((0, 1.0)).collect());
+	let mut work = 0;
+
+	for (value, probability) in order {
+		let contribution = contribution(options, value);
+		let mut next: HashMap<(usize, usize), Weights> = HashMap::new();
+		for ((assigned, kept), totals) in states {
+			let remaining = count - assigned;
+			let mut ways = 1.0;
+			for showing in 0..=remaining {
+				if showing > 0 {
+					ways *= to_f64(remaining - showing + 1)? / to_f64(showing)?;
+				}
+				let chance = ways * probability.powi(i32::try_from(showing)?);
+				let taken = showing.min(to_keep - kept);
+				let added = contribution
+					.checked_mul(DiceInt::try_from(taken)?)
+					.ok_or_else(|| anyhow!("Overflow calculating kept dice"))?;
+
+				work += totals.len();
+				check_combinations(work, 1)?;
+				let entry = next.entry((assigned + showing, kept + taken)).or_default();
+				for (total, total_probability) in &totals {
+					let total = total
+						.checked_add(added)
+						.ok_or_else(|| anyhow!("Overflow calculating kept dice"))?;
+					*entry.entry(total).or_insert(0.0) += total_probability * chance;
+				}
+			}
+		}
+		states = next;
+	}
+
+	let mut result = Weights::new();
+	for ((assigned, _), totals) in states {
+		if assigned == count {
+			for (total, probability) in totals {
+				*result.entry(total).or_insert(0.0) += probability;
+			}
+		}
+	}
+	Ok(result)
+}
+
+fn map_weights(weights: &Weights, f: impl Fn(DiceInt) -> Result<DiceInt>) -> Result<Weights> {
+	let mut result = Weights::new();
+	for (value, probability) in weights {
+		*result.entry(f(*value)?).or_insert(0.0) += probability;
+	}
+	Ok(result)
+}
+
+fn convolve(lhs: &Weights, rhs: &Weights) -> Result<Weights> {
+	use std::convert::TryFrom;
+
+	check_combinations(lhs.len(), rhs.len())?;
+	let (Some(lhs_min), Some(lhs_max), Some(rhs_min), Some(rhs_max)) = (
+		lhs.keys().next(),
+		lhs.keys().last(),
+		rhs.keys().next(),
+		rhs.keys().last(),
+	) else {
+		return Ok(Weights::new());
+	};
+	let lowest = lhs_min
+		.checked_add(*rhs_min)
+		.ok_or_else(|| anyhow!("Overflow summing dice"))?;
+	let highest = lhs_max
+		.checked_add(*rhs_max)
+		.ok_or_else(|| anyhow!("Overflow summing dice"))?;
+
+	// totals are collected in a dense array from lowest to highest, as maps are too slow
+	let span = usize::try_from(i64::from(highest) - i64::from(lowest) + 1)?;
+	check_combinations(span, 1)?;
+	let mut totals = vec![0.0; span];
+	for (lhs_value, lhs_probability) in lhs {
+		let lhs_offset = i64::from(*lhs_value) - i64::from(*lhs_min);
+		for (rhs_value, rhs_probability) in rhs {
+			let offset = lhs_offset + i64::from(*rhs_value) - i64::from(*rhs_min);
+			totals[usize::try_from(offset)?] += lhs_probability * rhs_probability;
+		}
+	}
+
+	let mut result = Weights::new();
+	for (total, probability) in (lowest..=highest).zip(totals) {
+		if probability > 0.0 {
+			result.insert(total, probability);
+		}
+	}
+	Ok(result)
+}
+
+// Sum of count independent copies, by repeated squaring
+fn convolve_n(weights: &Weights, mut count: DiceInt) -> Result<Weights> {
+	let mut result: Weights = std::iter::once((0, 1.0)).collect();
+	let mut power = weights.clone();
+	while count > 0 {
+		if count % 2 == 1 {
+			result = convolve(&result, &power)?;
+		}
+		count /= 2;
+		if count > 0 {
+			power = convolve(&power, &power)?;
+		}
+	}
+	Ok(result)
+}
+
+fn to_f64(n: usize) -> Result<f64> {
+	use std::convert::TryFrom;
+	Ok(f64::from(u32::try_from(n)?))
+}
+
+fn check_combinations(lhs: usize, rhs: usize) -> Result<()> {
+	ensure!(
+		lhs.saturating_mul(rhs) <= MAX_COMBINATIONS,
+		"Too many possible results to calculate exact odds"
+	);
+	Ok(())
+}
@@ -0,0 +1,320 @@
+use super::parser::{BinOp, Expr, Function, NestedDice};
+use super::{Budget, DiceRoll, Settings};
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use rand::Rng;
+use serde::Serialize;
+use std::fmt;
+
+// An expression after its dice have been rolled
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rolled {
+	Number(f64),
+	Dice(DiceRoll),
+	Neg(Box<Self>),
+	Binary(BinOp, Box<Self>, Box<Self>),
+	Group(Box<Self>),
+	Call(Function, Vec<Self>),
+	Labeled(Box<Self>, String),
+	// dice with a sub-expression for their count or sides, and the term they worked out to eg 3d6
+	NestedDice {
+		count: Box<Self>,
+		sides: Option<Box<Self>>,
+		options: String,
+		term: String,
+		dice: DiceRoll,
+	},
+	// each roll of a repeated expression eg 6x(4d6kh3)
+	Repeat {
+		count: Box<Self>,
+		rolls: Vec<Self>,
+	},
+}
+
+// What an expression works out to. Repeats give a list, which sum, min, max and sort take.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+	Number(f64),
+	List(Vec<f64>),
+}
+
+impl Value {
+	pub fn number(&self) -> Result<f64> {
+		match self {
+			Self::Number(n) => Ok(*n),
+			Self::List(_) => Err(anyhow!(
+				"Expected a number but got a list. Use sum, min or max to combine it."
+			)),
+		}
+	}
+
+	// Totals are whole numbers, rounded down like most tabletop rules. Fractions only last
+	// inside an expression, so ceil(7 / 2) is still 4 while 7 / 2 is 3.
+	#[must_use]
+	pub fn round_down(self) -> Self {
+		match self {
+			Self::Number(n) => Self::Number(whole_number(n)),
+			Self::List(list) => Self::List(list.into_iter().map(whole_number).collect()),
+		}
+	}
+
+	fn numbers(&self) -> &[f64] {
+		match self {
+			Self::Number(n) => std::slice::from_ref(n),
+			Self::List(list) => list,
+		}
+	}
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Number(n) => write!(f, "{}", format_number(*n)),
+			Self::List(list) => write!(
+				f,
+				"[{}]",
+				list.iter().copied().map(format_number).join(", ")
+			),
+		}
+	}
+}
+
+// Rolls every dice in an expression. The budget is shared with the rest of the roll,
+// so limits on dice and repeats cover the whole expression.
+pub fn roll(
+	expr: &Expr,
+	settings: &Settings,
+	budget: &mut Budget,
+	rng: &mut impl Rng,
+) -> Result<Rolled> {
+	Ok(match expr {
+		Expr::Number(n) => Rolled::Number(*n),
+		Expr::Dice(dice) => Rolled::Dice(DiceRoll::from_str(dice, settings, budget, rng)?),
+		Expr::Neg(inner) => Rolled::Neg(Box::new(roll(inner, settings, budget, rng)?)),
+		Expr::Binary(op, lhs, rhs) => {
+			let lhs = roll(lhs, settings, budget, rng)?;
+			let rhs = roll(rhs, settings, budget, rng)?;
+			Rolled::Binary(*op, Box::new(lhs), Box::new(rhs))
+		}
+		Expr::Group(inner) => Rolled::Group(Box::new(roll(inner, settings, budget, rng)?)),
+		Expr::Call(function, args) => Rolled::Call(
+			*function,
+			args.iter()
+				.map(|arg| roll(arg, settings, budget, rng))
+				.collect::<Result<Vec<Rolled>>>()?,
+		),
+		Expr::Labeled(inner, label) => {
+			Rolled::Labeled(Box::new(roll(inner, settings, budget, rng)?), label.clone())
+		}
+		Expr::NestedDice(nested) => {
+			let count = roll(&nested.count, settings, budget, rng)?;
+			let sides = nested
+				.sides
+				.as_ref()
+				.map(|sides| roll(sides, settings, budget, rng))
+				.transpose()?;
+			let term = nested.term(
+				count.value()?.number()?,
+				sides
+					.as_ref()
+					.map(|sides| sides.value()?.number())
+					.transpose()?,
+			)?;
+			let dice = DiceRoll::from_str(&term, settings, budget, rng)?;
+			Rolled::NestedDice {
+				count: Box::new(count),
+				sides: sides.map(Box::new),
+				options: nested.options.clone(),
+				term,
+				dice,
+			}
+		}
+		Expr::Repeat(expr, count) => {
+			let count = roll(count, settings, budget, rng)?;
+			let times = budget.repeat(count.value()?.number()?)?;
+			Rolled::Repeat {
+				count: Box::new(count),
+				rolls: (0..times)
+					.map(|_| roll(expr, settings, budget, rng))
+					.collect::<Result<Vec<Rolled>>>()?,
+			}
+		}
+	})
+}
+
+impl Rolled {
+	// Calls f with every dice roll in the expression, in order
+	pub fn for_each_dice(&self, f: &mut impl FnMut(&DiceRoll)) {
+		match self {
+			Self::Number(_) => {}
+			Self::Dice(dice) => f(dice),
+			Self::Neg(inner) | Self::Group(inner) | Self::Labeled(inner, _) => {
+				inner.for_each_dice(f);
+			}
+			Self::Binary(_, lhs, rhs) => {
+				lhs.for_each_dice(f);
+				rhs.for_each_dice(f);
+			}
+			Self::Call(_, args) => {
+				for arg in args {
+					arg.for_each_dice(f);
+				}
+			}
+			Self::NestedDice {
+				count, sides, dice, ..
+			} => {
+				count.for_each_dice(f);
+				if let Some(sides) = sides {
+					sides.for_each_dice(f);
+				}
+				f(dice);
+			}
+			Self::Repeat { count, rolls } => {
+				count.for_each_dice(f);
+				for rolled in rolls {
+					rolled.for_each_dice(f);
+				}
+			}
+		}
+	}
+
+	pub fn value(&self) -> Result<Value> {
+		Ok(match self {
+			Self::Number(n) => Value::Number(*n),
+			Self::Dice(dice) | Self::NestedDice { dice, .. } => {
+				Value::Number(f64::from(dice.val()?))
+			}
+			Self::Neg(inner) => Value::Number(-inner.value()?.number()?),
+			Self::Binary(op, lhs, rhs) => {
+				Value::Number(op.apply(lhs.value()?.number()?, rhs.value()?.number()?)?)
+			}
+			Self::Group(inner) | Self::Labeled(inner, _) => inner.value()?,
+			Self::Call(function, args) => function.apply_values(
+				&args
+					.iter()
+					.map(Self::value)
+					.collect::<Result<Vec<Value>>>()?,
+			)?,
+			// repeats of repeats are flattened into one list
+			Self::Repeat { rolls, .. } => {
+				let mut list = vec![];
+				for rolled in rolls {
+					list.extend_from_slice(rolled.value()?.numbers());
+				}
+				Value::List(list)
+			}
+		})
+	}
+}
+
+impl NestedDice {
+	// The plain dice term once the count and sides are known eg 3d6kh2
+	pub fn term(&self, count: f64, sides: Option<f64>) -> Result<String> {
+		let whole = |value: f64, name| {
+			ensure!(
+				value.fract() == 0.0,
+				"Dice {} must be a whole number. Got: {}",
+				name,
+				value
+			);
+			Ok(value)
+		};
+		let count = whole(count, "count")?;
+		Ok(match sides {
+			Some(sides) => format!("{}d{}{}", count, whole(sides, "sides")?, self.options),
+			None => format!("{}d{}", count, self.options),
+		})
+	}
+}
+
+impl BinOp {
+	pub fn apply(self, lhs: f64, rhs: f64) -> Result<f64> {
+		let value = match self {
+			Self::Add => lhs + rhs,
+			Self::Sub => lhs - rhs,
+			Self::Mul => lhs * rhs,
+			Self::Div => {
+				ensure!(rhs != 0.0, "Can't divide by zero");
+				lhs / rhs
+			}
+			Self::FloorDiv => {
+				ensure!(rhs != 0.0, "Can't divide by zero");
+				(lhs / rhs).floor()
+			}
+			Self::Rem => {
+				ensure!(rhs != 0.0, "Can't divide by zero");
+				lhs % rhs
+			}
+			Self::Pow => lhs.powf(rhs),
+		};
+		finite(value)
+	}
+}
+
+impl Function {
+	pub fn apply(self, args: &[f64]) -> Result<f64> {
+		let value = match self {
+			Self::Abs => args[0].abs(),
+			Self::Floor => args[0].floor(),
+			Self::Ceil => args[0].ceil(),
+			Self::Round => args[0].round(),
+			Self::Sqrt => args[0].sqrt(),
+			Self::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+			Self::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+			Self::Sum => args.iter().sum(),
+			Self::Sort => return Err(anyhow!("sort gives a list, not a number")),
+		};
+		finite(value)
+	}
+
+	// Functions of several numbers also take lists, eg max(6x(1d20)) or sum(2x(1d6), 3)
+	pub fn apply_values(self, args: &[Value]) -> Result<Value> {
+		let flattened = || args.iter().flat_map(Value::numbers).copied();
+		Ok(match self {
+			Self::Sort => Value::List(flattened().sorted_by(f64::total_cmp).collect()),
+			Self::Min | Self::Max | Self::Sum => {
+				Value::Number(self.apply(&flattened().collect::<Vec<f64>>())?)
+			}
+			_ => Value::Number(
+				self.apply(
+					&args
+						.iter()
+						.map(Value::number)
+						.collect::<Result<Vec<f64>>>()?,
+				)?,
+			),
+		})
+	}
+}
+
+// Rounds down, but not a total like 2.3 * 100 which float error puts just under a whole number
+pub fn whole_number(n: f64) -> f64 {
+	let nearest = n.round();
+	if (n - nearest).abs() < 1e-9 {
+		nearest
+	} else {
+		n.floor()
+	}
+}
+
+// Whole numbers as integers, and anything else to at most two decimal places eg 7.5 or 0.33
+fn format_number(n: f64) -> String {
+	if n.fract() == 0.0 {
+		return n.to_string();
+	}
+	let rounded = format!("{n:.2}");
+	rounded
+		.trim_end_matches('0')
+		.trim_end_matches('.')
+		.to_string()
+}
+
+fn finite(value: f64) -> Result<f64> {
+	if value.is_finite() {
+		Ok(value)
+	} else {
+		Err(anyhow!("Result out of range"))
+	}
+}
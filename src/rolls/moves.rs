@@ -0,0 +1,268 @@
+use super::{parser, DiceInt, RollResult, Settings, Style};
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Where a move's argument goes in its dice. At the start it's a pool size, eg {}d6kh1,
+// and anywhere else it's a modifier, eg 2d6{}.
+const ARGUMENT: &str = "{}";
+// Separates a pool move's dice from the dice it rolls for a pool of 0, eg {}d6kh1|2d6kl1
+const ZERO_POOL: char = '|';
+
+// A move rolls its dice and names the band the total lands in, like the 6-, 7-9 and 10+ of PbtA
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+	pub dice: String,
+	pub bands: Vec<Band>,
+	pub crit: Option<MoveCrit>,
+	// dice rolled for a pool of 0, which can't crit
+	#[serde(default)]
+	pub zero: Option<String>,
+}
+
+// Totals from min to max, either of which can be open, eg 10+ has no max
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Band {
+	pub min: Option<DiceInt>,
+	pub max: Option<DiceInt>,
+	pub label: String,
+}
+
+// Beats every band when enough dice show a face, eg two sixes in Blades in the Dark
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveCrit {
+	pub face: DiceInt,
+	pub count: usize,
+	pub label: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MoveRoll {
+	pub name: String,
+	pub result: RollResult,
+	pub outcome: Option<String>,
+}
+
+// Moves every server has, unless it adds its own with the same name
+pub const BUILTIN: [&str; 2] = ["pbta", "blades"];
+
+pub fn builtin(name: &str) -> Option<Move> {
+	let spec = match name {
+		"pbta" => "2d6{} 6-: Miss, 7-9: Weak hit, 10+: Strong hit",
+		"blades" => {
+			"{}d6kh1|2d6kl1 1-3: Bad outcome, 4-5: Partial success, 6: Full success, 6x2: Critical"
+		}
+		_ => return None,
+	};
+	parse_move(spec).ok()
+}
+
+// Parses dice then comma separated bands eg "2d6{} 6-: Miss, 7-9: Weak hit, 10+: Strong hit".
+// Pool dice like {}d6kh1 can be followed by dice for a pool of 0, eg {}d6kh1|2d6kl1.
+// A band is a total (6), a range (7-9), at most (6-) or at least (10+).
+// A face and count like 6x2 is a crit, which beats the bands when that many dice show the face.
+pub fn parse_move(spec: &str) -> Result<Move> {
+	let (dice, bands) = spec
+		.trim()
+		.split_once(char::is_whitespace)
+		.ok_or_else(|| anyhow!("Give the dice then the bands, eg 2d6{{}} 6-: Miss, 10+: Hit"))?;
+
+	let (dice, zero) = match dice.split_once(ZERO_POOL) {
+		Some((dice, zero)) => (dice, Some(zero.to_string())),
+		None => (dice, None),
+	};
+	ensure!(
+		dice.matches(ARGUMENT).count() <= 1,
+		"Dice can only have one {{}}. Got: {}",
+		dice
+	);
+	ensure!(
+		zero.is_none() || dice.starts_with(ARGUMENT),
+		"Only pool dice like {{}}d6kh1 can have dice for a pool of 0"
+	);
+
+	let mut parsed = Move {
+		dice: dice.to_string(),
+		bands: vec![],
+		crit: None,
+		zero,
+	};
+	for band in bands.split(',') {
+		let (range, label) = band
+			.split_once(':')
+			.ok_or_else(|| anyhow!("Bands look like 7-9: Weak hit. Got: {}", band.trim()))?;
+		let (range, label) = (range.trim(), label.trim().to_string());
+		ensure!(!label.is_empty(), "Missing a label for band {}", range);
+		let number = |text: &str| {
+			text.trim()
+				.parse::<DiceInt>()
+				.map_err(|e| anyhow!("Can't parse band {}, {}", range, e))
+		};
+
+		if let Some((face, count)) = range.split_once('x') {
+			ensure!(parsed.crit.is_none(), "Can only have one crit per move");
+			parsed.crit = Some(MoveCrit {
+				face: number(face)?,
+				count: count
+					.trim()
+					.parse()
+					.map_err(|e| anyhow!("Can't parse band {}, {}", range, e))?,
+				label,
+			});
+			continue;
+		}
+		let (min, max) = if let Some(min) = range.strip_suffix('+') {
+			(Some(number(min)?), None)
+		} else if let Some(max) = range.strip_suffix('-') {
+			(None, Some(number(max)?))
+		} else if let Some((min, max)) = range.split_once('-') {
+			(Some(number(min)?), Some(number(max)?))
+		} else {
+			let total = number(range)?;
+			(Some(total), Some(total))
+		};
+		parsed.bands.push(Band { min, max, label });
+	}
+	ensure!(!parsed.bands.is_empty(), "A move needs at least one band");
+	Ok(parsed)
+}
+
+// Rolls a move with a pool size like 3 for pool dice, or a modifier like +2 or -1 otherwise
+pub fn roll(
+	name: &str,
+	to_roll: &Move,
+	argument: &str,
+	settings: &Settings,
+	rng: &mut impl Rng,
+) -> Result<MoveRoll> {
+	let (expression, can_crit) = to_roll.expression(name, argument.trim())?;
+	let result = super::roll_statement(
+		&expression,
+		parser::parse_statement(&expression)?,
+		settings,
+		rng,
+	)?;
+	let outcome = to_roll.outcome(&result, can_crit)?;
+	Ok(MoveRoll {
+		name: name.to_string(),
+		result,
+		outcome,
+	})
+}
+
+impl Move {
+	// The expression to roll, and whether it can crit
+	fn expression(&self, name: &str, argument: &str) -> Result<(String, bool)> {
+		if let Some(dice) = self.dice.strip_prefix(ARGUMENT) {
+			let pool = argument.parse::<u32>().map_err(|_| {
+				anyhow!(
+					"Give {} a pool size of 0 or more dice, eg move {} 3. Got: {}",
+					name,
+					name,
+					argument
+				)
+			})?;
+			return match (pool, &self.zero) {
+				(0, Some(zero)) => Ok((zero.clone(), false)),
+				(0, None) => Err(anyhow!("{} needs a pool of at least 1 dice", name)),
+				(pool, _) => Ok((format!("{pool}{dice}"), true)),
+			};
+		}
+
+		let modifier = if argument.is_empty() {
+			String::new()
+		} else {
+			let modifier = argument.parse::<DiceInt>().map_err(|_| {
+				anyhow!(
+					"Give {} a whole number modifier, eg move {} +2. Got: {}",
+					name,
+					name,
+					argument
+				)
+			})?;
+			format!("{modifier:+}")
+		};
+		Ok(if self.dice.contains(ARGUMENT) {
+			(self.dice.replace(ARGUMENT, &modifier), true)
+		} else {
+			(format!("{}{}", self.dice, modifier), true)
+		})
+	}
+
+	fn outcome(&self, result: &RollResult, can_crit: bool) -> Result<Option<String>> {
+		if let Some(crit) = self.crit.as_ref().filter(|_| can_crit) {
+			// dropped dice count too, as Blades keeps one six but crits on two
+			let mut count = 0;
+			result.rolled.for_each_dice(&mut |dice| {
+				count += dice
+					.rolls
+					.iter()
+					.filter(|die| die.value == crit.face)
+					.count();
+			});
+			if count >= crit.count {
+				return Ok(Some(crit.label.clone()));
+			}
+		}
+
+		let total = result.value.number()?;
+		Ok(self
+			.bands
+			.iter()
+			.find(|band| band.contains(total))
+			.map(|band| band.label.clone()))
+	}
+}
+
+impl Band {
+	fn contains(&self, total: f64) -> bool {
+		self.min.is_none_or(|min| total >= f64::from(min))
+			&& self.max.is_none_or(|max| total <= f64::from(max))
+	}
+}
+
+impl fmt::Display for Band {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match (self.min, self.max) {
+			(Some(min), Some(max)) if min == max => write!(f, "{min}")?,
+			(Some(min), Some(max)) => write!(f, "{min}-{max}")?,
+			(Some(min), None) => write!(f, "{min}+")?,
+			(None, Some(max)) => write!(f, "{max}-")?,
+			(None, None) => write!(f, "any")?,
+		}
+		write!(f, ": {}", self.label)
+	}
+}
+
+impl fmt::Display for Move {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.dice)?;
+		if let Some(zero) = &self.zero {
+			write!(f, "{ZERO_POOL}{zero}")?;
+		}
+		write!(f, " {}", self.bands.iter().join(", "))?;
+		if let Some(crit) = &self.crit {
+			write!(f, ", {}x{}: {}", crit.face, crit.count, crit.label)?;
+		}
+		Ok(())
+	}
+}
+
+impl MoveRoll {
+	// eg "[3, 5] + 2 => **10**: **Strong hit**"
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		let result = self.result.render(style);
+		match &self.outcome {
+			Some(outcome) => format!("{}: {}", result, style.bold(outcome)),
+			None => result,
+		}
+	}
+
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		self.render(Style::Markdown)
+	}
+}
@@ -0,0 +1,245 @@
+use super::{DiceInt, Style};
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use rand::Rng;
+use serde::Serialize;
+
+// Most dice one narrative pool can roll
+const MAX_POOL_DICE: DiceInt = 100;
+
+// The dice of the Genesys and Star Wars FFG systems, whose faces show symbols rather than numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NarrativeDie {
+	Boost,       // b, blue d6
+	Setback,     // s, black d6
+	Ability,     // a, green d8
+	Difficulty,  // d, purple d8
+	Proficiency, // p, yellow d12
+	Challenge,   // c, red d12
+}
+
+// Symbols on a face, or a whole pool once they're added up.
+// Triumphs also count as a success and despairs as a failure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Symbols {
+	pub success: DiceInt,
+	pub failure: DiceInt,
+	pub advantage: DiceInt,
+	pub threat: DiceInt,
+	pub triumph: DiceInt,
+	pub despair: DiceInt,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NarrativeRoll {
+	pub dice: Vec<(NarrativeDie, Symbols)>,
+	pub net: Symbols, // after successes cancel failures and advantages cancel threats
+}
+
+impl NarrativeDie {
+	const fn from_letter(letter: char) -> Option<Self> {
+		match letter.to_ascii_lowercase() {
+			'b' => Some(Self::Boost),
+			's' => Some(Self::Setback),
+			'a' => Some(Self::Ability),
+			'd' => Some(Self::Difficulty),
+			'p' => Some(Self::Proficiency),
+			'c' => Some(Self::Challenge),
+			_ => None,
+		}
+	}
+
+	const fn emoji(self) -> &'static str {
+		match self {
+			Self::Boost => "🟦",
+			Self::Setback => "⬛",
+			Self::Ability => "🟩",
+			Self::Difficulty => "🟪",
+			Self::Proficiency => "🟨",
+			Self::Challenge => "🟥",
+		}
+	}
+
+	// Each face as symbol letters: S success, F failure, A advantage, T threat, R triumph, D despair
+	const fn faces(self) -> &'static [&'static str] {
+		match self {
+			Self::Boost => &["", "", "S", "SA", "AA", "A"],
+			Self::Setback => &["", "", "F", "F", "T", "T"],
+			Self::Ability => &["", "S", "S", "SS", "A", "A", "SA", "AA"],
+			Self::Difficulty => &["", "F", "FF", "T", "T", "T", "TT", "FT"],
+			Self::Proficiency => &[
+				"", "S", "S", "SS", "SS", "A", "SA", "SA", "SA", "AA", "AA", "R",
+			],
+			Self::Challenge => &[
+				"", "F", "F", "FF", "FF", "T", "T", "FT", "FT", "TT", "TT", "D",
+			],
+		}
+	}
+}
+
+impl Symbols {
+	fn from_face(face: &str) -> Self {
+		let count = |symbol| face.chars().filter(|c| *c == symbol).fold(0, |n, _| n + 1);
+		let triumph = count('R');
+		let despair = count('D');
+		Self {
+			success: count('S') + triumph,
+			failure: count('F') + despair,
+			advantage: count('A'),
+			threat: count('T'),
+			triumph,
+			despair,
+		}
+	}
+
+	const fn add(self, other: Self) -> Self {
+		Self {
+			success: self.success + other.success,
+			failure: self.failure + other.failure,
+			advantage: self.advantage + other.advantage,
+			threat: self.threat + other.threat,
+			triumph: self.triumph + other.triumph,
+			despair: self.despair + other.despair,
+		}
+	}
+
+	// Opposing symbols cancel one for one. Triumphs and despairs stay, as they have their own effects.
+	fn net(self) -> Self {
+		Self {
+			success: (self.success - self.failure).max(0),
+			failure: (self.failure - self.success).max(0),
+			advantage: (self.advantage - self.threat).max(0),
+			threat: (self.threat - self.advantage).max(0),
+			..self
+		}
+	}
+
+	// eg ✅✅⬆️, or ▫️ for a blank face
+	fn emoji(self) -> String {
+		use std::convert::TryFrom;
+
+		let symbols = [
+			(self.success - self.triumph, "✅"),
+			(self.failure - self.despair, "❌"),
+			(self.advantage, "⬆️"),
+			(self.threat, "⬇️"),
+			(self.triumph, "🏆"),
+			(self.despair, "💀"),
+		]
+		.iter()
+		.map(|(count, emoji)| emoji.repeat(usize::try_from(*count).unwrap_or(0)))
+		.collect::<String>();
+		if symbols.is_empty() {
+			"▫️".to_string()
+		} else {
+			symbols
+		}
+	}
+
+	// eg "2 success ✅, 1 advantage ⬆️"
+	fn summary(self) -> String {
+		let parts = [
+			(self.success, "success", "✅"),
+			(self.failure, "failure", "❌"),
+			(self.advantage, "advantage", "⬆️"),
+			(self.threat, "threat", "⬇️"),
+			(self.triumph, "triumph", "🏆"),
+			(self.despair, "despair", "💀"),
+		]
+		.iter()
+		.filter(|(count, ..)| *count > 0)
+		.map(|(count, name, emoji)| format!("{count} {name} {emoji}"))
+		.join(", ");
+		if parts.is_empty() {
+			"nothing".to_string()
+		} else {
+			parts
+		}
+	}
+}
+
+// Parses a pool like "2a1p1d1c", a count before each dice letter, defaulting to 1
+fn parse_pool(pool: &str) -> Result<Vec<NarrativeDie>> {
+	use std::convert::TryFrom;
+
+	let mut dice = vec![];
+	let mut count = String::new();
+	for c in pool.chars().filter(|c| !c.is_whitespace()) {
+		if c.is_ascii_digit() {
+			count.push(c);
+			continue;
+		}
+		let kind = NarrativeDie::from_letter(c).ok_or_else(|| {
+			anyhow!(
+				"Unknown narrative dice {}. Use b, s, a, d, p and c, eg 2a1p1d1c",
+				c
+			)
+		})?;
+		let n = if count.is_empty() {
+			1
+		} else {
+			count.parse::<DiceInt>()?
+		};
+		ensure!(
+			n <= MAX_POOL_DICE - DiceInt::try_from(dice.len())?,
+			"Can roll at most {} narrative dice",
+			MAX_POOL_DICE
+		);
+		dice.extend(std::iter::repeat_n(kind, usize::try_from(n)?));
+		count.clear();
+	}
+	ensure!(count.is_empty(), "Missing a dice letter after {}", count);
+	ensure!(!dice.is_empty(), "Give some dice to roll, eg 2a1p1d1c");
+	Ok(dice)
+}
+
+pub fn roll(pool: &str, rng: &mut impl Rng) -> Result<NarrativeRoll> {
+	let dice: Vec<(NarrativeDie, Symbols)> = parse_pool(pool)?
+		.into_iter()
+		.map(|die| {
+			let faces = die.faces();
+			(
+				die,
+				Symbols::from_face(faces[rng.gen_range(0..faces.len())]),
+			)
+		})
+		.collect();
+	let net = dice
+		.iter()
+		.fold(Symbols::default(), |total, (_, symbols)| {
+			total.add(*symbols)
+		})
+		.net();
+	Ok(NarrativeRoll { dice, net })
+}
+
+impl NarrativeRoll {
+	#[must_use]
+	pub const fn succeeded(&self) -> bool {
+		self.net.success > 0
+	}
+
+	// eg "🟩✅ 🟪⬇️ => **Success**: 1 success ✅"
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		format!(
+			"{} => {}: {}",
+			self.dice
+				.iter()
+				.map(|(die, symbols)| format!("{}{}", die.emoji(), symbols.emoji()))
+				.join(" "),
+			style.bold(if self.succeeded() {
+				"Success"
+			} else {
+				"Failure"
+			}),
+			self.net.summary()
+		)
+	}
+
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		self.render(Style::Markdown)
+	}
+}
@@ -1,115 +1,574 @@
-use super::DiceInt;
+use super::settings::{parse_faces, Face, Settings};
+use super::{DiceInt, Natural};
 use anyhow::{anyhow, ensure, Result};
 use regex::Regex;
+use serde::Serialize;
 
-#[derive(Eq, PartialEq, Debug, Clone)]
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Explode {
-	Standard,
-	Compounding,
+	Standard,    // !
+	Compounding, // !! adds to the exploding dice instead of rolling a new one
+	Penetrating, // !p each extra dice is 1 lower
 }
 
-#[derive(Eq, PartialEq, Debug)]
+// Which dice explode, and at most how many extra rolls they make for the whole term
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+pub struct Explosion {
+	pub kind: Explode,
+	pub condition: Condition, // defaults to the max side
+	pub limit: Option<DiceInt>,
+}
+
+impl Explosion {
+	#[must_use]
+	pub const fn allows(&self, extra_rolls: DiceInt) -> bool {
+		match self.limit {
+			Some(limit) => extra_rolls < limit,
+			None => true,
+		}
+	}
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Keep {
+	Highest(DiceInt),     // khX
+	Lowest(DiceInt),      // klX
+	DropHighest(DiceInt), // dhX
+	DropLowest(DiceInt),  // dlX
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compare {
+	Equal,          // =
+	NotEqual,       // <>, or != after an option which takes a condition
+	LessThan,       // <
+	LessOrEqual,    // <=
+	GreaterThan,    // >
+	GreaterOrEqual, // >=
+}
+
+impl Compare {
+	#[must_use]
+	pub const fn symbol(self) -> &'static str {
+		match self {
+			Self::Equal => "=",
+			Self::NotEqual => "<>",
+			Self::LessThan => "<",
+			Self::LessOrEqual => "<=",
+			Self::GreaterThan => ">",
+			Self::GreaterOrEqual => ">=",
+		}
+	}
+
+	#[must_use]
+	pub fn from_symbol(symbol: &str) -> Option<Self> {
+		match symbol {
+			"=" => Some(Self::Equal),
+			"<>" | "!=" => Some(Self::NotEqual),
+			"<" => Some(Self::LessThan),
+			"<=" => Some(Self::LessOrEqual),
+			">" => Some(Self::GreaterThan),
+			">=" => Some(Self::GreaterOrEqual),
+			_ => None,
+		}
+	}
+}
+
+// A test against a single dice value eg <3
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+pub struct Condition {
+	pub compare: Compare,
+	pub value: DiceInt,
+}
+
+impl Condition {
+	#[must_use]
+	pub const fn matches(&self, dice: DiceInt) -> bool {
+		match self.compare {
+			Compare::Equal => dice == self.value,
+			Compare::NotEqual => dice != self.value,
+			Compare::LessThan => dice < self.value,
+			Compare::LessOrEqual => dice <= self.value,
+			Compare::GreaterThan => dice > self.value,
+			Compare::GreaterOrEqual => dice >= self.value,
+		}
+	}
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+pub struct Reroll {
+	pub condition: Condition,
+	pub until: bool, // rr keeps rerolling until the condition no longer matches, r rerolls once
+}
+
+// Counts dice which pass the filters as successes rather than summing them.
+// Dice matching the failure condition subtract a success each.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+pub struct Count {
+	pub failure: Option<Condition>,
+}
+
+// A d% rolled as a units dice and tens dice. Bonus dice roll extra tens dice and keep the best result,
+// penalty dice keep the worst, and one of each cancel out.
+#[derive(Eq, PartialEq, Debug, Clone, Default, Serialize)]
+pub struct Percentile {
+	pub bonus: DiceInt,   // bX
+	pub penalty: DiceInt, // pX
+}
+
+// Fate dice, shown as + − ▢
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fate {
+	Standard, // dF, -1, 0 and +1
+	Scaled,   // dF3 or dF with the guild's scaled Fate setting, -3, 0 and +3
+}
+
+// Most bonus or penalty dice a percentile roll can have
+const MAX_EXTRA_TENS: DiceInt = 10;
+
+#[derive(Eq, PartialEq, Debug, Serialize)]
 pub struct Options {
 	pub number_of_dice: DiceInt,  // Xd
 	pub dice_sides: Vec<DiceInt>, // dX -> [1, 2, ..X]
-	pub explode: Option<Explode>,
-	pub min: Option<DiceInt>,
-	pub max: Option<DiceInt>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub symbols: Vec<Option<String>>, // symbol for each side, empty unless a face has one
+	pub explode: Option<Explosion>,
+	pub keep: Option<Keep>,
+	pub reroll: Option<Reroll>,
+	pub count: Option<Count>,
+	pub filters: Vec<Condition>, // dice must match every filter to count, eg >=15
+	pub crit: Option<Condition>, // natural rolls which are critical successes eg cs>19
+	pub fumble: Option<Condition>, // natural rolls which are fumbles eg cf1
+	pub percentile: Option<Percentile>, // d% rolls tens and units dice
+	pub fate: Option<Fate>,
 }
 
-pub fn parse(mut str: &str) -> Result<Options> {
-	let mut dice_sides: Option<Vec<DiceInt>> = None;
-	let mut explode: Option<Explode> = None;
-	let mut min = None;
-	let mut max = None;
+pub fn parse(str: &str, settings: &Settings) -> Result<Options> {
+	let mut explode: Option<(Explode, Option<Condition>)> = None;
+	let mut explode_limit = None;
+	let mut keep: Option<Keep> = None;
+	let mut reroll: Option<Reroll> = None;
+	let mut count: Option<Count> = None;
+	let mut filters = vec![];
+	let mut crit: Option<Condition> = None;
+	let mut fumble: Option<Condition> = None;
+	let mut tens = Percentile::default();
 
-	str = str.trim();
+	let str = str.trim();
 	ensure!(!str.is_empty(), "Can't roll an empty string.");
 
-	let parts = split_keeping_delimiters(&ROLL_OPTION_DELIMITER_REGEX, str);
-	let number_of_dice = parts[0]
+	let (number_of_dice, sides) = str
+		.split_once('d')
+		.ok_or_else(|| anyhow!("Must set dice sides (eg d20)"))?;
+	let number_of_dice = number_of_dice
 		.parse::<DiceInt>()
-		.map_err(|e| anyhow!("Can't parse number of dice {}, {}", parts[0], e))?;
+		.map_err(|e| anyhow!("Can't parse number of dice {}, {}", number_of_dice, e))?;
+	let (faces, fate, rest) = parse_sides(sides, settings)?;
+	let percentile = sides.starts_with('%');
+
+	let parts = split_keeping_delimiters(&ROLL_OPTION_DELIMITER_REGEX, rest);
 
 	let mut idx = 0;
-	loop {
-		idx += 1;
-		if idx >= parts.len() {
-			break;
-		}
-		let option = RollOption::parse(parts[idx])
-			.ok_or_else(|| anyhow!("Unknown roll option {}", parts[idx]))?;
+	while let Some(part) = parts.get(idx) {
+		let option =
+			RollOption::parse(part).ok_or_else(|| anyhow!("Unknown roll option {}", part))?;
 		match &option {
-			RollOption::Explode(val) => {
-				explode = Some(val.clone());
+			RollOption::Explode(_) | RollOption::ExplodeOn => {
+				ensure!(
+					explode.is_none(),
+					"Can only use one explode option per roll"
+				);
+				explode = Some(if let RollOption::Explode(kind) = &option {
+					// an optional condition eg !>8 explodes on 9s and 10s
+					let condition = parse_optional_condition(&parts, &mut idx, &option)?;
+					(kind.clone(), condition)
+				} else {
+					idx += 1;
+					let condition = parse_condition(&parts, &mut idx, &option)?;
+					(Explode::Standard, Some(condition))
+				});
+			}
+			RollOption::Filter => {
+				filters.push(parse_condition(&parts, &mut idx, &option)?);
+			}
+			RollOption::Reroll { until } => {
+				ensure!(reroll.is_none(), "Can only use one reroll option per roll");
+				idx += 1;
+				let condition = parse_condition(&parts, &mut idx, &option)?;
+				reroll = Some(Reroll {
+					condition,
+					until: *until,
+				});
+			}
+			RollOption::Count => {
+				count.get_or_insert(Count { failure: None });
+			}
+			RollOption::Crit => set_condition(&mut crit, &parts, &mut idx, &option)?,
+			RollOption::Fumble => set_condition(&mut fumble, &parts, &mut idx, &option)?,
+			RollOption::Failure => {
+				let count = count.get_or_insert(Count { failure: None });
+				set_condition(&mut count.failure, &parts, &mut idx, &option)?;
 			}
 			RollOption::Valued(valued) => {
 				idx += 1;
 
 				let value = parts
 					.get(idx)
-					.ok_or_else(|| anyhow!("Missing value for {:?}", option))?;
-
-				if let (Valued::DiceSides, &"F") = (valued, value) {
-					dice_sides = Some(vec![-3, 0, 3]);
-					continue;
-				}
-
-				let value = value.parse::<DiceInt>()?;
+					.ok_or_else(|| anyhow!("Missing value for {:?}", option))?
+					.parse::<DiceInt>()?;
 				match valued {
-					Valued::DiceSides => {
-						ensure!(
-							value < super::MAX_DICE_SIDES,
-							"Must have < {} dice sides to roll. Tried: {}",
-							super::MAX_DICE_SIDES,
-							number_of_dice
-						);
-						dice_sides = Some((1..=value).collect())
-					}
-					Valued::LessThan => max = Some(value),
-					Valued::GreaterThan => min = Some(value),
+					Valued::KeepHighest => set_keep(&mut keep, Keep::Highest(value))?,
+					Valued::KeepLowest => set_keep(&mut keep, Keep::Lowest(value))?,
+					Valued::DropHighest => set_keep(&mut keep, Keep::DropHighest(value))?,
+					Valued::DropLowest => set_keep(&mut keep, Keep::DropLowest(value))?,
+					Valued::ExplosionLimit => explode_limit = Some(value),
+					Valued::Bonus => tens.bonus = value,
+					Valued::Penalty => tens.penalty = value,
 				}
 			}
 		}
+		idx += 1;
 	}
 
-	let dice_sides = dice_sides.ok_or_else(|| anyhow!("Must set dice sides (eg d20)"))?;
+	let explode = explosion(explode, explode_limit, &faces)?;
+	let (crit, fumble) = crit_ranges(crit, fumble, &faces, settings);
 
+	let (dice_sides, symbols) = split_faces(faces);
+
+	let options = Options {
+		number_of_dice,
+		dice_sides,
+		symbols,
+		explode,
+		keep,
+		reroll,
+		count,
+		filters,
+		crit,
+		fumble,
+		percentile: percentile_tens(percentile, tens)?,
+		fate,
+	};
+	options.validate()?;
+
+	Ok(options)
+}
+
+// Parses the sides after the d, returning the faces, whether they're Fate dice and the remaining options.
+// Sides are a number (d20), a face list (d{1,1,2}), Fate (dF or dF3) or a named dice (dHit).
+fn parse_sides<'a>(
+	sides: &'a str,
+	settings: &Settings,
+) -> Result<(Vec<Face>, Option<Fate>, &'a str)> {
+	if let Some(list) = sides.strip_prefix('{') {
+		let (list, rest) = list
+			.split_once('}')
+			.ok_or_else(|| anyhow!("Missing closing }} for dice faces"))?;
+		return Ok((parse_faces(list)?, None, rest));
+	}
+
+	let digits = sides.len() - sides.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+	if digits > 0 {
+		let value = sides[..digits].parse::<DiceInt>()?;
+		ensure!(
+			value < super::MAX_DICE_SIDES,
+			"Must have < {} dice sides to roll. Tried: {}",
+			super::MAX_DICE_SIDES,
+			value
+		);
+		return Ok((numeric_faces(1..=value), None, &sides[digits..]));
+	}
+
+	if let Some((faces, rest)) = settings.named_dice(sides) {
+		return Ok((faces.to_vec(), None, rest));
+	}
+	if let Some(rest) = sides.strip_prefix('%') {
+		return Ok((numeric_faces(1..=100), None, rest));
+	}
+	if let Some(rest) = sides.strip_prefix('F') {
+		let (fate, rest) = match rest.strip_prefix('3') {
+			Some(rest) => (Fate::Scaled, rest),
+			None if settings.scaled_fate => (Fate::Scaled, rest),
+			None => (Fate::Standard, rest),
+		};
+		let step = if fate == Fate::Scaled { 3 } else { 1 };
+		return Ok((numeric_faces(vec![-step, 0, step]), Some(fate), rest));
+	}
+
+	Err(anyhow!(
+		"Must set dice sides (eg d20, d{{1,1,2}} or a named dice). Tried: d{}",
+		sides
+	))
+}
+
+// Dice explode on their max side unless given a condition
+fn explosion(
+	explode: Option<(Explode, Option<Condition>)>,
+	limit: Option<DiceInt>,
+	faces: &[Face],
+) -> Result<Option<Explosion>> {
+	let Some((kind, condition)) = explode else {
+		ensure!(
+			limit.is_none(),
+			"Can only limit explosions for exploding dice"
+		);
+		return Ok(None);
+	};
+	let max_side = faces
+		.iter()
+		.map(|face| face.value)
+		.max()
+		.ok_or_else(|| anyhow!("Must have at least one dice side"))?;
+
+	Ok(Some(Explosion {
+		kind,
+		condition: condition.unwrap_or(Condition {
+			compare: Compare::Equal,
+			value: max_side,
+		}),
+		limit,
+	}))
+}
+
+// Bonus and penalty dice only make sense for d%
+fn percentile_tens(percentile: bool, tens: Percentile) -> Result<Option<Percentile>> {
+	if percentile {
+		return Ok(Some(tens));
+	}
 	ensure!(
-		!dice_sides.is_empty(),
-		"Must have >= 0 sides on dice to roll. Tried: {:?}",
-		dice_sides
-	);
-	ensure!(
-		number_of_dice > 0,
-		"Must have >= 0 dice to roll. Tried: {}",
-		number_of_dice
+		tens == Percentile::default(),
+		"Bonus and penalty dice are only for percentile dice, eg 1d%b1"
 	);
+	Ok(None)
+}
+
+// Face values, and their symbols if any face has one
+fn split_faces(faces: Vec<Face>) -> (Vec<DiceInt>, Vec<Option<String>>) {
+	let values = faces.iter().map(|face| face.value).collect();
+	let symbols = if faces.iter().any(|face| face.symbol.is_some()) {
+		faces.into_iter().map(|face| face.symbol).collect()
+	} else {
+		vec![]
+	};
+	(values, symbols)
+}
+
+// Guilds with crits on mark natural 20s and 1s on plain d20s which don't set their own ranges
+fn crit_ranges(
+	crit: Option<Condition>,
+	fumble: Option<Condition>,
+	faces: &[Face],
+	settings: &Settings,
+) -> (Option<Condition>, Option<Condition>) {
+	if settings.crits && crit.is_none() && fumble.is_none() && is_d20(faces) {
+		let natural = |value| {
+			Some(Condition {
+				compare: Compare::Equal,
+				value,
+			})
+		};
+		return (natural(20), natural(1));
+	}
+	(crit, fumble)
+}
+
+fn is_d20(faces: &[Face]) -> bool {
+	faces.len() == 20
+		&& faces
+			.iter()
+			.zip(1..)
+			.all(|(face, value)| face.value == value && face.symbol.is_none())
+}
+
+fn numeric_faces(values: impl IntoIterator<Item = DiceInt>) -> Vec<Face> {
+	values
+		.into_iter()
+		.map(|value| Face {
+			value,
+			symbol: None,
+		})
+		.collect()
+}
+
+impl Options {
+	// Whether a dice value passes the filters eg <15 and >=3
+	#[must_use]
+	pub fn passes_filters(&self, dice: DiceInt) -> bool {
+		self.filters.iter().all(|filter| filter.matches(dice))
+	}
+
+	// Whether a natural roll is a crit or a fumble. Crits win where the ranges overlap.
+	#[must_use]
+	pub fn natural(&self, roll: DiceInt) -> Option<Natural> {
+		let matches = |range: &Option<Condition>| range.as_ref().is_some_and(|it| it.matches(roll));
+		if matches(&self.crit) {
+			Some(Natural::Crit)
+		} else if matches(&self.fumble) {
+			Some(Natural::Fumble)
+		} else {
+			None
+		}
+	}
+
+	fn validate(&self) -> Result<()> {
+		let Self {
+			number_of_dice,
+			dice_sides,
+			explode,
+			keep,
+			reroll,
+			count,
+			filters,
+			crit,
+			fumble,
+			percentile,
+			..
+		} = self;
+
+		ensure!(
+			!dice_sides.is_empty(),
+			"Must have >= 0 sides on dice to roll. Tried: {:?}",
+			dice_sides
+		);
+		ensure!(
+			*number_of_dice > 0,
+			"Must have >= 0 dice to roll. Tried: {}",
+			number_of_dice
+		);
+		ensure!(
+			*number_of_dice < super::MAX_ROLLED_DICE,
+			"Must have < {} dice to roll. Tried: {}",
+			super::MAX_ROLLED_DICE,
+			number_of_dice
+		);
+		ensure!(
+			dice_sides.len() < (super::MAX_DICE_SIDES as usize),
+			"Must have < {} dice sides to roll. Tried: {}",
+			super::MAX_DICE_SIDES,
+			number_of_dice
+		);
+
+		if let Some(Explosion {
+			limit: Some(limit), ..
+		}) = explode
+		{
+			ensure!(
+				*limit >= 0,
+				"Explosion limit can't be negative. Tried: {}",
+				limit
+			);
+		}
+
+		if let Some(Percentile { bonus, penalty }) = percentile {
+			ensure!(
+				*number_of_dice == 1,
+				"Percentile dice roll one at a time, eg 1d%. Use repeats or d100 for more."
+			);
+			ensure!(
+				explode.is_none()
+					&& keep.is_none()
+					&& reroll.is_none()
+					&& count.is_none()
+					&& filters.is_empty()
+					&& crit.is_none()
+					&& fumble.is_none(),
+				"Percentile dice only take bonus (b) and penalty (p) options"
+			);
+			ensure!(
+				(0..=MAX_EXTRA_TENS).contains(bonus) && (0..=MAX_EXTRA_TENS).contains(penalty),
+				"Can have between 0 and {} bonus or penalty dice",
+				MAX_EXTRA_TENS
+			);
+		}
+
+		if let Some(Reroll {
+			condition,
+			until: true,
+		}) = reroll
+		{
+			ensure!(
+				!dice_sides.iter().all(|side| condition.matches(*side)),
+				"Can't reroll until {:?} {} as every side matches",
+				condition.compare,
+				condition.value
+			);
+		}
+
+		Ok(())
+	}
+}
+
+// Sets the condition for an option which can only be used once, eg cs>19 or f1
+fn set_condition(
+	condition: &mut Option<Condition>,
+	parts: &[&str],
+	idx: &mut usize,
+	option: &RollOption,
+) -> Result<()> {
 	ensure!(
-		number_of_dice < super::MAX_ROLLED_DICE,
-		"Must have < {} dice to roll. Tried: {}",
-		super::MAX_ROLLED_DICE,
-		number_of_dice
+		condition.is_none(),
+		"Can only use one {:?} option per roll",
+		option
 	);
+	*idx += 1;
+	*condition = Some(parse_condition(parts, idx, option)?);
+	Ok(())
+}
+
+fn set_keep(keep: &mut Option<Keep>, value: Keep) -> Result<()> {
 	ensure!(
-		dice_sides.len() < (super::MAX_DICE_SIDES as usize),
-		"Must have < {} dice sides to roll. Tried: {}",
-		super::MAX_DICE_SIDES,
-		number_of_dice
+		keep.is_none(),
+		"Can only use one keep or drop option per roll. Tried: {:?} and {:?}",
+		keep,
+		value
 	);
-
-	Ok(Options {
-		number_of_dice,
-		dice_sides,
-		explode,
-		min,
-		max,
-	})
+	*keep = Some(value);
+	Ok(())
 }
 
+// Options which don't take a value, eg s, must be delimiters so they split from any option after them
 lazy_static! {
 	static ref ROLL_OPTION_DELIMITER_REGEX: Regex =
-		Regex::new(r"(\d+|!{1, 2}|F)").expect("Hardcoded regex");
+		Regex::new(r"(\d+|cs|cf|!p|!=|!{1, 2}|<>|<=|>=|[<>=]|kh|kl|k|dh|dl|l|rr|r|s|f|b|p)")
+			.expect("Hardcoded regex");
+}
+
+// Parses an optional comparison followed by a value starting at parts[idx], leaving idx on the value.
+// A missing comparison means equality, eg r1 rerolls 1s.
+fn parse_condition(parts: &[&str], idx: &mut usize, option: &RollOption) -> Result<Condition> {
+	let compare = parts.get(*idx).and_then(|part| Compare::from_symbol(part));
+	if compare.is_some() {
+		*idx += 1;
+	}
+
+	let value = parts
+		.get(*idx)
+		.ok_or_else(|| anyhow!("Missing value for {:?}", option))?;
+	Ok(Condition {
+		compare: compare.unwrap_or(Compare::Equal),
+		value: value
+			.parse::<DiceInt>()
+			.map_err(|e| anyhow!("Can't parse value {} for {:?}, {}", value, option, e))?,
+	})
+}
+
+// Parses a condition after parts[idx] if one follows, leaving idx on its value
+fn parse_optional_condition(
+	parts: &[&str],
+	idx: &mut usize,
+	option: &RollOption,
+) -> Result<Option<Condition>> {
+	match parts.get(*idx + 1) {
+		Some(next) if Compare::from_symbol(next).is_some() || next.parse::<DiceInt>().is_ok() => {
+			*idx += 1;
+			Ok(Some(parse_condition(parts, idx, option)?))
+		}
+		_ => Ok(None),
+	}
 }
 
 fn split_keeping_delimiters<'a>(r: &Regex, text: &'a str) -> Vec<&'a str> {
@@ -132,25 +591,48 @@ fn split_keeping_delimiters<'a>(r: &Regex, text: &'a str) -> Vec<&'a str> {
 #[derive(Debug)]
 enum RollOption {
 	Valued(Valued),
+	Filter,
 	Explode(Explode),
+	ExplodeOn, // != explodes on one value eg !=1, rather than meaning not equal
+	Reroll { until: bool },
+	Count,
+	Failure,
+	Crit,
+	Fumble,
 }
 
 #[derive(Debug)]
 enum Valued {
-	DiceSides,
-	LessThan,
-	GreaterThan,
+	KeepHighest,
+	KeepLowest,
+	DropHighest,
+	DropLowest,
+	ExplosionLimit,
+	Bonus,
+	Penalty,
 }
 
 impl RollOption {
 	fn parse(str: &str) -> Option<Self> {
 		match str {
-			"d" => Some(Self::Valued(Valued::DiceSides)),
-			"<" => Some(Self::Valued(Valued::LessThan)),
-			">" => Some(Self::Valued(Valued::GreaterThan)),
+			"!=" => Some(Self::ExplodeOn),
+			"k" | "kh" => Some(Self::Valued(Valued::KeepHighest)),
+			"kl" => Some(Self::Valued(Valued::KeepLowest)),
+			"dh" => Some(Self::Valued(Valued::DropHighest)),
+			"dl" => Some(Self::Valued(Valued::DropLowest)),
 			"!" => Some(Self::Explode(Explode::Standard)),
 			"!!" => Some(Self::Explode(Explode::Compounding)),
-			_ => None,
+			"!p" => Some(Self::Explode(Explode::Penetrating)),
+			"l" => Some(Self::Valued(Valued::ExplosionLimit)),
+			"b" => Some(Self::Valued(Valued::Bonus)),
+			"p" => Some(Self::Valued(Valued::Penalty)),
+			"r" => Some(Self::Reroll { until: false }),
+			"rr" => Some(Self::Reroll { until: true }),
+			"s" => Some(Self::Count),
+			"f" => Some(Self::Failure),
+			"cs" => Some(Self::Crit),
+			"cf" => Some(Self::Fumble),
+			_ => Compare::from_symbol(str).map(|_| Self::Filter),
 		}
 	}
 }
@@ -0,0 +1,664 @@
+use super::Compare;
+use anyhow::{anyhow, ensure, Result};
+use serde::Serialize;
+
+// Grammar, loosest binding first:
+//
+// expr    := term (('+' | '-') term)*
+// term    := unary (('*' | '/' | '//' | '%') unary)*
+// unary   := ('-' | '+') unary | power
+// power   := labeled ('^' unary)?
+// labeled := primary ('[' label ']')?, where a label on a modifier like 1d20+5 [to hit]
+//            moves to the dice before it
+// primary := number | dice | repeat | function '(' expr (',' expr)* ')' | '(' expr ')' ('d' sides)?
+// dice    := digits? 'd' ('%' | sides), where only d% can leave out the count
+// repeat  := (digits | '(' expr ')') 'x' '(' expr ')' | 'repeat' '(' expr ',' expr ')'
+// sides   := '(' expr ')' options | options, where options runs until whitespace or an operator
+//            outside of a {face, list} and is handed to options::parse
+//
+// A whole roll is an expression, an optional target to compare it against, then an optional comment:
+//
+// statement := expr ('adv' | 'dis')? (('vs' | compare) expr)? ('#' comment)?
+//
+// where compare is one of < <= > >= = <>, and the comment runs to the end.
+// A whole roll of one die ending in a comparison, eg 1d100<=45, is also a target.
+// Anywhere else, or when working out odds, the comparison filters the dice as usual.
+// adv and dis roll the expression's first 1d20 twice keeping the higher or lower, and alone they're 2d20kh1 or 2d20kl1.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+	Number(f64),
+	Dice(String),
+	Neg(Box<Self>),
+	Binary(BinOp, Box<Self>, Box<Self>),
+	Group(Box<Self>),
+	Call(Function, Vec<Self>),
+	NestedDice(Box<NestedDice>),
+	Labeled(Box<Self>, String),   // eg 1d20 [to hit]
+	Repeat(Box<Self>, Box<Self>), // (expression, count) eg 6x(4d6kh3)
+}
+
+// Everything in one roll eg "1d100 <= 45 # spot hidden"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+	pub expr: Expr,
+	pub target: Option<Target>,
+	pub comment: Option<String>,
+}
+
+// What a roll is compared against eg vs 15. vs means the roll must meet or beat the target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+	pub compare: Compare,
+	pub value: Expr,
+}
+
+// Dice with a sub-expression for their count or sides eg (1d4)d6 or 1d(2d6).
+// Options holds everything after the d, or after the sides when they're a sub-expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NestedDice {
+	pub count: Expr,
+	pub sides: Option<Expr>,
+	pub options: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinOp {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	FloorDiv, // integer division, rounding down eg 7 // 2 = 3
+	Rem,
+	Pow,
+}
+
+impl BinOp {
+	#[must_use]
+	pub const fn symbol(self) -> &'static str {
+		match self {
+			Self::Add => "+",
+			Self::Sub => "-",
+			Self::Mul => "*",
+			Self::Div => "/",
+			Self::FloorDiv => "//",
+			Self::Rem => "%",
+			Self::Pow => "^",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Function {
+	Abs,
+	Floor,
+	Ceil,
+	Round,
+	Sqrt,
+	Min,
+	Max,
+	Sum,
+	Sort,
+}
+
+impl Function {
+	fn from_name(name: &str) -> Option<Self> {
+		match name.to_ascii_lowercase().as_str() {
+			"abs" => Some(Self::Abs),
+			"floor" => Some(Self::Floor),
+			"ceil" => Some(Self::Ceil),
+			"round" => Some(Self::Round),
+			"sqrt" => Some(Self::Sqrt),
+			"min" => Some(Self::Min),
+			"max" => Some(Self::Max),
+			"sum" => Some(Self::Sum),
+			"sort" => Some(Self::Sort),
+			_ => None,
+		}
+	}
+
+	#[must_use]
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Abs => "abs",
+			Self::Floor => "floor",
+			Self::Ceil => "ceil",
+			Self::Round => "round",
+			Self::Sqrt => "sqrt",
+			Self::Min => "min",
+			Self::Max => "max",
+			Self::Sum => "sum",
+			Self::Sort => "sort",
+		}
+	}
+
+	// (min, max) number of arguments
+	const fn arity(self) -> (usize, usize) {
+		match self {
+			Self::Min | Self::Max | Self::Sum | Self::Sort => (1, usize::MAX),
+			_ => (1, 1),
+		}
+	}
+}
+
+// Parses a lone expression, ignoring any comment
+pub fn parse(src: &str) -> Result<Expr> {
+	let statement = statement(src, false)?;
+	ensure!(
+		statement.target.is_none(),
+		"Can only compare against a target when rolling"
+	);
+	Ok(statement.expr)
+}
+
+// Parses an expression, its target and its trailing comment, eg "1d20+5 vs 15 # stealth"
+pub fn parse_statement(src: &str) -> Result<Statement> {
+	statement(src, true)
+}
+
+// unspaced is whether a roll of one die like 1d100<=45 compares against a target
+fn statement(src: &str, unspaced: bool) -> Result<Statement> {
+	let mut parser = Parser { src, pos: 0 };
+	ensure!(!src.trim().is_empty(), "Can't roll an empty expression.");
+	let mut expr = parser.expr()?;
+	let unspaced = if unspaced {
+		unspaced_target(&mut expr)
+	} else {
+		None
+	};
+	if let Some(keep) = parser.advantage() {
+		ensure!(
+			roll_twice(&mut expr, keep)?,
+			"adv and dis need a 1d20 to roll twice, eg 1d20+3 adv"
+		);
+	}
+	let target = match (unspaced, parser.target()?) {
+		(Some(_), Some(_)) => return Err(anyhow!("Can only compare a roll against one target")),
+		(unspaced, target) => target.or(unspaced),
+	};
+	let comment = if parser.eat('#') {
+		Some(parser.rest().trim().to_string())
+	} else {
+		None
+	};
+	parser.skip_whitespace();
+	if comment.is_none() && parser.peek().is_some() {
+		return Err(parser.unexpected());
+	}
+	Ok(Statement {
+		expr,
+		target,
+		comment: comment.filter(|comment| !comment.is_empty()),
+	})
+}
+
+// Splits several expressions separated by ; or , outside of brackets eg "1d20+7; 2d6+4".
+// Only ; ends a comment, so comments can contain commas.
+pub fn split_expressions(src: &str) -> Vec<&str> {
+	let mut parts = vec![];
+	let mut depth = 0_usize;
+	let mut in_comment = false;
+	let mut start = 0;
+	for (idx, c) in src.char_indices() {
+		match c {
+			'(' | '{' | '[' if !in_comment => depth += 1,
+			')' | '}' | ']' if !in_comment => depth = depth.saturating_sub(1),
+			'#' if depth == 0 => in_comment = true,
+			';' | ',' if depth == 0 && (c == ';' || !in_comment) => {
+				parts.push(&src[start..idx]);
+				start = idx + 1;
+				in_comment = false;
+			}
+			_ => {}
+		}
+	}
+	parts.push(&src[start..]);
+	parts
+}
+
+struct Parser<'a> {
+	src: &'a str,
+	pos: usize,
+}
+
+impl<'a> Parser<'a> {
+	fn rest(&self) -> &'a str {
+		&self.src[self.pos..]
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.rest().chars().next()
+	}
+
+	fn bump(&mut self) -> Option<char> {
+		let c = self.peek()?;
+		self.pos += c.len_utf8();
+		Some(c)
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.peek().is_some_and(char::is_whitespace) {
+			self.bump();
+		}
+	}
+
+	// skips whitespace, then consumes c if it's next
+	fn eat(&mut self, c: char) -> bool {
+		self.skip_whitespace();
+		self.eat_raw(c)
+	}
+
+	// consumes c if it's next, without skipping whitespace
+	fn eat_raw(&mut self, c: char) -> bool {
+		if self.peek() == Some(c) {
+			self.bump();
+			true
+		} else {
+			false
+		}
+	}
+
+	fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+		let start = self.pos;
+		while self.peek().is_some_and(&f) {
+			self.bump();
+		}
+		&self.src[start..self.pos]
+	}
+
+	fn unexpected(&self) -> anyhow::Error {
+		self.peek().map_or_else(
+			|| anyhow!("Expression ended unexpectedly"),
+			|c| anyhow!("Unexpected '{}' at position {}", c, self.pos + 1),
+		)
+	}
+
+	// skips whitespace, then consumes word if it's next as a whole word, ignoring case
+	fn eat_keyword(&mut self, word: &str) -> bool {
+		self.skip_whitespace();
+		let rest = self.rest();
+		let found = rest
+			.get(..word.len())
+			.is_some_and(|it| it.eq_ignore_ascii_case(word))
+			&& !rest[word.len()..].starts_with(char::is_alphanumeric);
+		if found {
+			self.pos += word.len();
+		}
+		found
+	}
+
+	// The keep option for an adv or dis keyword, if one is next
+	fn advantage(&mut self) -> Option<&'static str> {
+		if self.eat_keyword("adv") {
+			Some("kh1")
+		} else if self.eat_keyword("dis") {
+			Some("kl1")
+		} else {
+			None
+		}
+	}
+
+	// An optional comparison against a target after the expression eg vs 15 or <= 45
+	fn target(&mut self) -> Result<Option<Target>> {
+		let compare = if self.eat_keyword("vs") {
+			Compare::GreaterOrEqual
+		} else {
+			let symbol = self.take_while(|c| matches!(c, '<' | '>' | '=' | '!'));
+			if symbol.is_empty() {
+				return Ok(None);
+			}
+			Compare::from_symbol(symbol).ok_or_else(|| anyhow!("Unknown comparison {}", symbol))?
+		};
+		Ok(Some(Target {
+			compare,
+			value: self.expr()?,
+		}))
+	}
+
+	fn expr(&mut self) -> Result<Expr> {
+		let mut lhs = self.term()?;
+		loop {
+			let op = if self.eat('+') {
+				BinOp::Add
+			} else if self.eat('-') {
+				BinOp::Sub
+			} else {
+				return Ok(lhs);
+			};
+			let rhs = label_dice(&mut lhs, self.term()?);
+			lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+		}
+	}
+
+	fn term(&mut self) -> Result<Expr> {
+		let mut lhs = self.unary()?;
+		loop {
+			let op = if self.eat('*') {
+				BinOp::Mul
+			} else if self.eat('/') {
+				if self.eat_raw('/') {
+					BinOp::FloorDiv
+				} else {
+					BinOp::Div
+				}
+			} else if self.eat('%') {
+				BinOp::Rem
+			} else {
+				return Ok(lhs);
+			};
+			let rhs = label_dice(&mut lhs, self.unary()?);
+			lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+		}
+	}
+
+	fn unary(&mut self) -> Result<Expr> {
+		if self.eat('-') {
+			Ok(Expr::Neg(Box::new(self.unary()?)))
+		} else if self.eat('+') {
+			self.unary()
+		} else {
+			self.power()
+		}
+	}
+
+	fn power(&mut self) -> Result<Expr> {
+		let base = self.labeled()?;
+		if self.eat('^') {
+			let exponent = self.unary()?;
+			return Ok(Expr::Binary(BinOp::Pow, Box::new(base), Box::new(exponent)));
+		}
+		Ok(base)
+	}
+
+	fn labeled(&mut self) -> Result<Expr> {
+		let expr = self.primary()?;
+		if !self.eat('[') {
+			return Ok(expr);
+		}
+		let label = self.take_while(|c| c != ']').trim().to_string();
+		ensure!(self.eat_raw(']'), "Missing closing ] for label {}", label);
+		Ok(Expr::Labeled(Box::new(expr), label))
+	}
+
+	fn primary(&mut self) -> Result<Expr> {
+		self.skip_whitespace();
+		match self.peek() {
+			Some('(') => {
+				self.bump();
+				let inner = self.expr()?;
+				ensure!(self.eat(')'), "Missing closing parenthesis");
+				let group = Expr::Group(Box::new(inner));
+				if self.eat_raw('d') {
+					return self.nested_dice(group);
+				}
+				if self.eat_raw('x') {
+					return self.repeat(group);
+				}
+				Ok(group)
+			}
+			Some(c) if c.is_ascii_digit() || c == '.' => self.number_or_dice(),
+			// d% is one percentile die, like 1d%
+			Some('d') if self.src[self.pos..].starts_with("d%") => {
+				let start = self.pos;
+				self.pos += "d%".len();
+				self.dice_options()?;
+				Ok(Expr::Dice(format!("1{}", &self.src[start..self.pos])))
+			}
+			Some(c) if c.is_alphabetic() => self.call(),
+			_ => Err(self.unexpected()),
+		}
+	}
+
+	fn number_or_dice(&mut self) -> Result<Expr> {
+		let start = self.pos;
+		let digits = self.take_while(|c| c.is_ascii_digit() || c == '.');
+
+		if self.eat_raw('d') {
+			if self.peek() == Some('(') {
+				let count = number(digits)?;
+				return self.nested_dice(count);
+			}
+			// % is the remainder operator anywhere else
+			self.eat_raw('%');
+			self.dice_options()?;
+			return Ok(Expr::Dice(self.src[start..self.pos].to_string()));
+		}
+		if self.eat_raw('x') {
+			return self.repeat(number(digits)?);
+		}
+
+		number(digits)
+	}
+
+	// Dice after the d, once the count has been parsed
+	fn nested_dice(&mut self, count: Expr) -> Result<Expr> {
+		let sides = if self.eat_raw('(') {
+			let sides = self.expr()?;
+			ensure!(self.eat(')'), "Missing closing parenthesis for dice sides");
+			Some(Expr::Group(Box::new(sides)))
+		} else {
+			None
+		};
+		let options = self.dice_options()?.to_string();
+
+		Ok(Expr::NestedDice(Box::new(NestedDice {
+			count,
+			sides,
+			options,
+		})))
+	}
+
+	// The bracketed expression after a repeat's x, once the count has been parsed
+	fn repeat(&mut self, count: Expr) -> Result<Expr> {
+		ensure!(self.eat('('), "Expected '(' after x, eg 6x(4d6kh3)");
+		let expr = self.expr()?;
+		ensure!(self.eat(')'), "Missing closing parenthesis for repeat");
+		Ok(Expr::Repeat(Box::new(expr), Box::new(count)))
+	}
+
+	// Options after a dice's d, which run until whitespace or an operator
+	fn dice_options(&mut self) -> Result<&'a str> {
+		let start = self.pos;
+		// face lists like d{-1,0,1} can contain terminators
+		loop {
+			self.take_while(|c| !is_dice_terminator(c) && c != '{');
+			if !self.eat_raw('{') {
+				break;
+			}
+			self.take_while(|c| c != '}');
+			ensure!(self.eat_raw('}'), "Missing closing }} for dice faces");
+		}
+		Ok(&self.src[start..self.pos])
+	}
+
+	fn call(&mut self) -> Result<Expr> {
+		if let Some(keep) = self.advantage() {
+			return Ok(Expr::Dice(format!("2d20{keep}")));
+		}
+		let name = self.take_while(char::is_alphanumeric);
+		if name.eq_ignore_ascii_case("repeat") {
+			ensure!(self.eat('('), "Expected '(' after {}", name);
+			let expr = self.expr()?;
+			ensure!(
+				self.eat(','),
+				"repeat needs an expression and a count, eg repeat(4d6kh3, 6)"
+			);
+			let count = self.expr()?;
+			ensure!(self.eat(')'), "Missing closing parenthesis for {}", name);
+			return Ok(Expr::Repeat(Box::new(expr), Box::new(count)));
+		}
+		let function =
+			Function::from_name(name).ok_or_else(|| anyhow!("Unknown function '{}'", name))?;
+		ensure!(self.eat('('), "Expected '(' after {}", name);
+
+		let mut args = vec![self.expr()?];
+		while self.eat(',') {
+			args.push(self.expr()?);
+		}
+		ensure!(self.eat(')'), "Missing closing parenthesis for {}", name);
+
+		let (min, max) = function.arity();
+		ensure!(
+			args.len() >= min && args.len() <= max,
+			"Wrong number of arguments for {}: {}",
+			name,
+			args.len()
+		);
+
+		Ok(Expr::Call(function, args))
+	}
+}
+
+// Turns the first 1d20 in an expression into 2d20 keeping one, eg 2d20kh1 for advantage.
+// Returns whether there was one.
+fn roll_twice(expr: &mut Expr, keep: &str) -> Result<bool> {
+	Ok(match expr {
+		Expr::Dice(dice) => match dice.strip_prefix("1d20") {
+			Some(rest) if !rest.starts_with(|c: char| c.is_ascii_digit()) => {
+				*dice = if has_keep(rest) {
+					// 1d20kh1 adv already says which die to keep
+					ensure!(
+						has_option(rest, keep) || has_option(rest, &keep.replace('h', "")),
+						"adv and dis already keep a die, so they can't be used with {}",
+						dice
+					);
+					format!("2d20{rest}")
+				} else {
+					format!("2d20{keep}{rest}")
+				};
+				true
+			}
+			_ => false,
+		},
+		Expr::Neg(inner)
+		| Expr::Group(inner)
+		| Expr::Labeled(inner, _)
+		| Expr::Repeat(inner, _) => roll_twice(inner, keep)?,
+		Expr::Binary(_, lhs, rhs) => roll_twice(lhs, keep)? || roll_twice(rhs, keep)?,
+		Expr::Call(_, args) => {
+			for arg in args {
+				if roll_twice(arg, keep)? {
+					return Ok(true);
+				}
+			}
+			false
+		}
+		Expr::Number(_) | Expr::NestedDice(_) => false,
+	})
+}
+
+// Whether dice options include keep or drop, eg kh1 or dl2
+fn has_keep(options: &str) -> bool {
+	options.contains('k') || options.contains("dh") || options.contains("dl")
+}
+
+// Whether dice options include an option with its whole value, so kh1 isn't found in kh10
+fn has_option(options: &str, option: &str) -> bool {
+	options
+		.match_indices(option)
+		.any(|(i, _)| !options[i + option.len()..].starts_with(|c: char| c.is_ascii_digit()))
+}
+
+// A label after a modifier belongs to the dice before it, so 1d20+5 [to hit] labels the 1d20.
+// Moves the label from rhs to the last dice in lhs without a label, returning what's left of rhs.
+fn label_dice(lhs: &mut Expr, rhs: Expr) -> Expr {
+	match rhs {
+		Expr::Labeled(inner, label) if !has_dice(&inner) => match last_dice(lhs) {
+			Some(dice) => {
+				let unlabeled = std::mem::replace(dice, Expr::Number(0.0));
+				*dice = Expr::Labeled(Box::new(unlabeled), label);
+				*inner
+			}
+			None => Expr::Labeled(inner, label),
+		},
+		rhs => rhs,
+	}
+}
+
+fn has_dice(expr: &Expr) -> bool {
+	match expr {
+		Expr::Dice(_) | Expr::NestedDice(_) => true,
+		Expr::Number(_) => false,
+		Expr::Neg(inner) | Expr::Group(inner) | Expr::Labeled(inner, _) => has_dice(inner),
+		Expr::Binary(_, lhs, rhs) | Expr::Repeat(lhs, rhs) => has_dice(lhs) || has_dice(rhs),
+		Expr::Call(_, args) => args.iter().any(has_dice),
+	}
+}
+
+// The rightmost dice, or group or call containing dice, which doesn't have a label yet
+fn last_dice(expr: &mut Expr) -> Option<&mut Expr> {
+	match expr {
+		Expr::Labeled(..) | Expr::Number(_) => None,
+		Expr::Neg(inner) => last_dice(inner),
+		Expr::Binary(_, lhs, rhs) => {
+			if has_dice(rhs) {
+				last_dice(rhs)
+			} else {
+				last_dice(lhs)
+			}
+		}
+		Expr::Dice(_)
+		| Expr::NestedDice(_)
+		| Expr::Group(_)
+		| Expr::Call(..)
+		| Expr::Repeat(..) => {
+			if has_dice(expr) {
+				Some(expr)
+			} else {
+				None
+			}
+		}
+	}
+}
+
+// A roll of one die ending in a comparison, eg 1d100<=45, compares against a target like
+// 1d100 <= 45 does. Only the whole roll counts, so 1d20>10 + 1d6 still filters the d20.
+fn unspaced_target(expr: &mut Expr) -> Option<Target> {
+	let whole = match expr {
+		Expr::Labeled(inner, _) => inner.as_mut(),
+		_ => &mut *expr,
+	};
+	if let Expr::Dice(dice) = whole {
+		if let Some((rest, compare, value)) = single_die_comparison(dice) {
+			*dice = rest;
+			return Some(Target {
+				compare,
+				value: Expr::Number(value),
+			});
+		}
+	}
+	None
+}
+
+// Splits one die ending in a comparison like 1d100<=45 or 2d20kh1>=15 into the dice and the comparison.
+// Comparisons after an option like 1d6!>4 or 1d20cs>19 are part of that option.
+fn single_die_comparison(dice: &str) -> Option<(String, Compare, f64)> {
+	let digits = dice.len() - dice.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+	let (rest, value) = dice.split_at(dice.len() - digits);
+	let (rest, compare) = ["<=", ">=", "<>", "<", ">", "="]
+		.iter()
+		.find_map(|symbol| Some((rest.strip_suffix(symbol)?, Compare::from_symbol(symbol)?)))?;
+	let (count, sides) = rest.split_once('d')?;
+	let one_die = matches!(count, "" | "1") || rest.ends_with("kh1") || rest.ends_with("kl1");
+	let after_sides = rest.ends_with(|c: char| c.is_ascii_digit() || c == '}' || c == '%');
+	if digits == 0 || sides.is_empty() || !one_die || !after_sides {
+		return None;
+	}
+	Some((rest.to_string(), compare, value.parse().ok()?))
+}
+
+fn number(digits: &str) -> Result<Expr> {
+	digits
+		.parse::<f64>()
+		.map(Expr::Number)
+		.map_err(|_| anyhow!("Can't parse number '{}'", digits))
+}
+
+const fn is_dice_terminator(c: char) -> bool {
+	matches!(
+		c,
+		'+' | '-' | '*' | '/' | '%' | '^' | '(' | ')' | ',' | '[' | '#'
+	) || c.is_whitespace()
+}
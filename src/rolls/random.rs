@@ -0,0 +1,117 @@
+use super::Style;
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Serialize;
+
+// Most items to choose from or shuffle, and coins to flip at once
+const MAX_ITEMS: usize = 100;
+const MAX_FLIPS: usize = super::MAX_ROLLED_DICE as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coin {
+	Heads,
+	Tails,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Flips {
+	pub coins: Vec<Coin>,
+}
+
+// Items separated by commas, or by spaces if there are no commas, eg "Alice Bob Carol"
+fn items(list: &str) -> Result<Vec<&str>> {
+	let items: Vec<&str> = if list.contains(',') {
+		list.split(',').map(str::trim).collect()
+	} else {
+		list.split_whitespace().collect()
+	};
+	ensure!(
+		!items.is_empty() && items.iter().all(|item| !item.is_empty()),
+		"Give some items separated by commas or spaces, eg a, b, c"
+	);
+	ensure!(
+		items.len() <= MAX_ITEMS,
+		"Can use at most {} items",
+		MAX_ITEMS
+	);
+	Ok(items)
+}
+
+// Picks one item. An item like 3:sword is weighted 3 times as likely as an item without a weight.
+pub fn choose(list: &str, rng: &mut impl Rng) -> Result<String> {
+	let (weights, options): (Vec<u32>, Vec<&str>) = items(list)?
+		.into_iter()
+		.map(|item| match item.split_once(':') {
+			Some((weight, option)) if weight.trim().chars().all(|c| c.is_ascii_digit()) => {
+				let weight = weight
+					.trim()
+					.parse::<u32>()
+					.map_err(|e| anyhow!("Can't parse weight for {}, {}", item, e))?;
+				Ok((weight, option.trim()))
+			}
+			_ => Ok((1, item)),
+		})
+		.collect::<Result<Vec<_>>>()?
+		.into_iter()
+		.unzip();
+	let index = WeightedIndex::new(&weights)
+		.map_err(|_| anyhow!("Weights must be whole numbers and can't all be 0"))?;
+	Ok(options[index.sample(rng)].to_string())
+}
+
+// Puts the items in a random order, eg for turn order
+pub fn shuffle(list: &str, rng: &mut impl Rng) -> Result<Vec<String>> {
+	let mut items = items(list)?;
+	items.shuffle(rng);
+	Ok(items.into_iter().map(str::to_string).collect())
+}
+
+pub fn flip(count: usize, rng: &mut impl Rng) -> Result<Flips> {
+	ensure!(
+		(1..=MAX_FLIPS).contains(&count),
+		"Can flip between 1 and {} coins",
+		MAX_FLIPS
+	);
+	Ok(Flips {
+		coins: (0..count)
+			.map(|_| if rng.gen() { Coin::Heads } else { Coin::Tails })
+			.collect(),
+	})
+}
+
+impl Flips {
+	#[must_use]
+	pub fn heads(&self) -> usize {
+		self.coins
+			.iter()
+			.filter(|coin| **coin == Coin::Heads)
+			.count()
+	}
+
+	// eg "**Heads**", or "H, T, H => **2 heads**, 1 tails" for several coins
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		match self.coins.as_slice() {
+			[Coin::Heads] => style.bold("Heads"),
+			[Coin::Tails] => style.bold("Tails"),
+			coins => format!(
+				"{} => {}, {} tails",
+				coins
+					.iter()
+					.map(|coin| if *coin == Coin::Heads { "H" } else { "T" })
+					.join(", "),
+				style.bold(&format!("{} heads", self.heads())),
+				coins.len() - self.heads()
+			),
+		}
+	}
+
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		self.render(Style::Markdown)
+	}
+}
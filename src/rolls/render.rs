@@ -0,0 +1,258 @@
+use super::{DiceRoll, Die, Fate, RollResult, Rolled, Value};
+use anyhow::Result;
+use itertools::Itertools;
+use std::fmt;
+
+// How struck out and highlighted parts of a roll are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+	Markdown, // Discord markdown eg ~~1~~ and **5**
+	Plain,    // plain text eg (1) and 5
+}
+
+impl Style {
+	#[must_use]
+	pub fn strike(self, text: &str) -> String {
+		match self {
+			Self::Markdown => format!("~~{text}~~"),
+			Self::Plain => format!("({text})"),
+		}
+	}
+
+	#[must_use]
+	pub fn italic(self, text: &str) -> String {
+		match self {
+			Self::Markdown => format!("*{text}*"),
+			Self::Plain => format!("[{text}]"),
+		}
+	}
+
+	#[must_use]
+	pub fn bold(self, text: &str) -> String {
+		match self {
+			Self::Markdown => format!("**{text}**"),
+			Self::Plain => text.to_string(),
+		}
+	}
+}
+
+impl RollResult {
+	// eg "attack: [4, 1] + 2 => **7**". A lone single dice just shows its result,
+	// unless it was rerolled so the replaced roll shows too.
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		let (single_simple_roll, summary) = match &self.rolled {
+			Rolled::Dice(roll) => (
+				roll.options.explode.is_none()
+					&& roll.options.reroll.is_none()
+					&& roll.options.count.is_none()
+					&& roll.options.percentile.is_none()
+					&& roll.options.number_of_dice == 1,
+				roll.successes_summary().or_else(|| roll.symbols_summary()),
+			),
+			Rolled::NestedDice { dice: roll, .. } => (
+				false,
+				roll.successes_summary().or_else(|| roll.symbols_summary()),
+			),
+			_ => (false, None),
+		};
+		let summary = summary
+			.or_else(|| self.fate_ladder())
+			.unwrap_or_else(|| self.value.to_string());
+		let mut result = style.bold(&summary);
+		match (self.crit, self.fumble) {
+			(true, true) => result += " (crit, fumble)",
+			(true, false) => result += " (crit)",
+			(false, true) => result += " (fumble)",
+			(false, false) => {}
+		}
+		// eg ">= 15: **success** by 2"
+		if let Some(check) = &self.check {
+			let target = match &check.target {
+				Rolled::Number(_) => check.target.render(style),
+				target => format!("{} ({})", target.render(style), check.target_value),
+			};
+			let margin = if check.margin > 0.0 {
+				format!(" by {}", check.margin)
+			} else {
+				String::new()
+			};
+			result = format!(
+				"{} {} {}: {}{}",
+				result,
+				check.compare.symbol(),
+				target,
+				style.bold(check.verdict()),
+				margin
+			);
+		}
+
+		let rolled = if single_simple_roll {
+			result
+		} else {
+			format!("{} => {}", self.rolled.render(style), result)
+		};
+		match &self.comment {
+			Some(comment) => format!("{comment}: {rolled}"),
+			None => rolled,
+		}
+	}
+
+	// Totals of rolls with standard Fate dice on the Fate ladder eg "Great (+4)"
+	fn fate_ladder(&self) -> Option<String> {
+		use num_traits::cast::ToPrimitive;
+
+		let mut fate = false;
+		self.rolled.for_each_dice(&mut |dice| {
+			fate |= dice.options.fate == Some(Fate::Standard);
+		});
+		let total = match self.value {
+			Value::Number(total) if fate && total.fract() == 0.0 => total.to_i32()?,
+			_ => return None,
+		};
+
+		let name = match total {
+			i32::MIN..=-3 => "Beyond Terrible",
+			-2 => "Terrible",
+			-1 => "Poor",
+			0 => "Mediocre",
+			1 => "Average",
+			2 => "Fair",
+			3 => "Good",
+			4 => "Great",
+			5 => "Superb",
+			6 => "Fantastic",
+			7 => "Epic",
+			8 => "Legendary",
+			_ => "Beyond Legendary",
+		};
+		Some(format!("{name} ({total:+})"))
+	}
+
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		self.render(Style::Markdown)
+	}
+
+	#[must_use]
+	pub fn to_plain(&self) -> String {
+		self.render(Style::Plain)
+	}
+
+	pub fn to_json(&self) -> Result<String> {
+		Ok(serde_json::to_string(self)?)
+	}
+}
+
+impl Rolled {
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		match self {
+			Self::Number(n) => n.to_string(),
+			Self::Dice(dice) => dice.render(style),
+			Self::Neg(inner) => format!("-{}", inner.render(style)),
+			Self::Binary(op, lhs, rhs) => format!(
+				"{} {} {}",
+				lhs.render(style),
+				op.symbol(),
+				rhs.render(style)
+			),
+			Self::Group(inner) => format!("({})", inner.render(style)),
+			Self::Labeled(inner, label) => {
+				format!("{} {}", inner.render(style), style.italic(label))
+			}
+			Self::Call(function, args) => format!(
+				"{}({})",
+				function.name(),
+				args.iter().map(|arg| arg.render(style)).join(", ")
+			),
+			// shows each step eg ([3])d6 → 3d6 [4, 2, 5]
+			Self::NestedDice {
+				count,
+				sides,
+				options,
+				term,
+				dice,
+			} => format!(
+				"{}d{}{} → {} {}",
+				count.render(style),
+				sides
+					.as_ref()
+					.map_or_else(String::new, |sides| sides.render(style)),
+				options,
+				term,
+				dice.render(style)
+			),
+			// eg 3x([4, 2]; [6, 1]; [3, 3])
+			Self::Repeat { count, rolls } => format!(
+				"{}x({})",
+				count.render(style),
+				rolls.iter().map(|rolled| rolled.render(style)).join("; ")
+			),
+		}
+	}
+}
+
+impl fmt::Display for Rolled {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.render(Style::Markdown))
+	}
+}
+
+impl DiceRoll {
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		// percentile dice show their tens dice then their units dice eg tens [~~70~~, 30] units [4]
+		if let Some((units, tens)) = self
+			.options
+			.percentile
+			.as_ref()
+			.and_then(|_| self.rolls.split_last())
+		{
+			return format!(
+				"tens [{}] units [{}]",
+				tens.iter().map(|it| self.render_die(it, style)).join(", "),
+				self.render_die(units, style)
+			);
+		}
+		format!(
+			"[{}]",
+			self.rolls
+				.iter()
+				.map(|it| self.render_die(it, style))
+				.join(", ")
+		)
+	}
+
+	// Shows rerolled values struck out before the final value eg ~~1~~→4,
+	// marks dice added by or compounded with explosions eg 3!, and bolds crits and fumbles
+	fn render_die(&self, die: &Die, style: Style) -> String {
+		let mut face = if self.options.fate.is_some() {
+			match die.value.signum() {
+				1 => "+",
+				-1 => "−",
+				_ => "▢",
+			}
+			.to_string()
+		} else {
+			die.symbol.clone().unwrap_or_else(|| die.value.to_string())
+		};
+		if die.exploded {
+			face.push('!');
+		}
+		if die.natural.is_some() {
+			face = style.bold(&face);
+		}
+		let value = if self.check_dice(die) {
+			face
+		} else {
+			style.strike(&face)
+		};
+
+		die.rerolled
+			.iter()
+			.map(|rerolled| style.strike(&rerolled.to_string()))
+			.chain(std::iter::once(value))
+			.join("→")
+	}
+}
@@ -0,0 +1,86 @@
+use super::parser::{self, Expr, Target};
+use super::{Compare, RollResult, Settings, Style};
+use anyhow::{anyhow, ensure, Result};
+use rand::Rng;
+use serde::Serialize;
+
+// Trait rolls succeed on 4 or more unless given a target, and each 4 over is a raise
+const DEFAULT_TARGET: f64 = 4.0;
+const RAISE: f64 = 4.0;
+
+// A Savage Worlds trait roll. The trait dice and an exploding d6 wild dice are rolled, keeping the higher.
+#[derive(Debug, Serialize)]
+pub struct SavageRoll {
+	pub result: RollResult,
+	pub raises: u32,
+	pub critical_failure: bool, // both dice rolled a natural 1
+}
+
+// Rolls a trait dice with a modifier and target eg "d8+1 vs 6 # shooting"
+pub fn trait_roll(msg: &str, settings: &Settings, rng: &mut impl Rng) -> Result<SavageRoll> {
+	use num_traits::cast::ToPrimitive;
+
+	let trimmed = msg.trim();
+	let rest = trimmed
+		.strip_prefix('1')
+		.unwrap_or(trimmed)
+		.strip_prefix('d')
+		.ok_or_else(|| anyhow!("Start with the trait dice, eg d8+1"))?;
+	let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+	ensure!(digits > 0, "Start with the trait dice, eg d8+1");
+	let (sides, rest) = rest.split_at(digits);
+
+	// the exploding dice, their max and the target all come from the usual roll code
+	let mut statement =
+		parser::parse_statement(&format!("max(1d{sides}! [trait], 1d6! [wild]){rest}"))?;
+	statement.target.get_or_insert(Target {
+		compare: Compare::GreaterOrEqual,
+		value: Expr::Number(DEFAULT_TARGET),
+	});
+	let mut result = super::roll_statement(msg, statement, settings, rng)?;
+
+	let mut naturals = vec![];
+	result.rolled.for_each_dice(&mut |dice| {
+		naturals.push(dice.rolls.first().map(|die| die.value));
+	});
+	let critical_failure = naturals.starts_with(&[Some(1), Some(1)]);
+	if critical_failure {
+		// fails whatever the modifiers, so the target doesn't matter
+		result.check = None;
+	}
+	let raises = match &result.check {
+		Some(check) if check.success => (check.margin / RAISE).floor().to_u32().unwrap_or(0),
+		_ => 0,
+	};
+
+	Ok(SavageRoll {
+		result,
+		raises,
+		critical_failure,
+	})
+}
+
+impl SavageRoll {
+	// eg "max([5] *trait*, [6!, 3!] *wild*) + 1 => **10** >= 4: **success** by 6, 1 raise"
+	#[must_use]
+	pub fn render(&self, style: Style) -> String {
+		let result = self.result.render(style);
+		if self.critical_failure {
+			format!("{}, {}", result, style.bold("critical failure"))
+		} else if self.raises > 0 {
+			format!(
+				"{}, {} raise{}",
+				result,
+				self.raises,
+				if self.raises == 1 { "" } else { "s" }
+			)
+		} else {
+			result
+		}
+	}
+
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		self.render(Style::Markdown)
+	}
+}
@@ -0,0 +1,138 @@
+use super::moves::{self, Move};
+use super::DiceInt;
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Per-guild roll configuration, eg named dice like dHit
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Settings {
+	#[serde(default)]
+	pub dice: BTreeMap<String, Vec<Face>>,
+	// d20s without their own crit or fumble range crit on 20 and fumble on 1
+	#[serde(default)]
+	pub crits: bool,
+	// dF rolls -3, 0 and +3 rather than -1, 0 and +1
+	#[serde(default)]
+	pub scaled_fate: bool,
+	// moves by lowercase name, eg PbtA style bands for d;move hack +1
+	#[serde(default)]
+	pub moves: BTreeMap<String, Move>,
+}
+
+// One face of a dice. Symbol faces like hit=1 show their symbol instead of their value.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Face {
+	pub value: DiceInt,
+	pub symbol: Option<String>,
+}
+
+impl Settings {
+	// The longest named dice which text starts with, eg Hit for "Hitkh2"
+	#[must_use]
+	pub fn named_dice<'a>(&self, text: &'a str) -> Option<(&[Face], &'a str)> {
+		self.dice
+			.iter()
+			.filter(|(name, _)| text.starts_with(name.as_str()))
+			.max_by_key(|(name, _)| name.len())
+			.map(|(name, faces)| (faces.as_slice(), &text[name.len()..]))
+	}
+
+	pub fn add_dice(&mut self, name: &str, faces: &str) -> Result<()> {
+		ensure!(
+			name.chars().next().is_some_and(char::is_uppercase)
+				&& name.chars().all(char::is_alphabetic)
+				&& name != "F",
+			"Dice names must be letters starting with a capital, and can't be F. Tried: {}",
+			name
+		);
+		self.dice.insert(name.to_string(), parse_faces(faces)?);
+		Ok(())
+	}
+
+	// This server's move with a name, or a built in one like pbta or blades
+	#[must_use]
+	pub fn find_move(&self, name: &str) -> Option<Move> {
+		let name = name.to_lowercase();
+		self.moves
+			.get(&name)
+			.cloned()
+			.or_else(|| moves::builtin(&name))
+	}
+
+	// Built in moves then this server's, which replace built in ones of the same name
+	#[must_use]
+	pub fn all_moves(&self) -> BTreeMap<String, Move> {
+		moves::BUILTIN
+			.iter()
+			.filter_map(|name| Some((name.to_string(), moves::builtin(name)?)))
+			.chain(self.moves.clone())
+			.collect()
+	}
+
+	pub fn add_move(&mut self, name: &str, spec: &str) -> Result<()> {
+		ensure!(
+			!name.is_empty() && name.chars().all(char::is_alphabetic),
+			"Move names must be letters. Tried: {}",
+			name
+		);
+		self.moves
+			.insert(name.to_lowercase(), moves::parse_move(spec)?);
+		Ok(())
+	}
+}
+
+impl fmt::Display for Face {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.symbol {
+			Some(symbol) => write!(f, "{}={}", symbol, self.value),
+			None => write!(f, "{}", self.value),
+		}
+	}
+}
+
+// Parses a comma separated face list eg "1, 1, 2" or "miss, hit=1, crit=2".
+// A symbol without a value is worth 0.
+pub fn parse_faces(list: &str) -> Result<Vec<Face>> {
+	let faces = list
+		.split(',')
+		.map(|face| {
+			let face = face.trim();
+			ensure!(!face.is_empty(), "Empty dice face in {}", list);
+			if let Ok(value) = face.parse::<DiceInt>() {
+				return Ok(Face {
+					value,
+					symbol: None,
+				});
+			}
+			let (symbol, value) = match face.split_once('=') {
+				Some((symbol, value)) => (
+					symbol.trim(),
+					value
+						.trim()
+						.parse::<DiceInt>()
+						.map_err(|e| anyhow!("Can't parse value for face {}, {}", face, e))?,
+				),
+				None => (face, 0),
+			};
+			ensure!(
+				!symbol.is_empty() && !symbol.contains(['{', '}']),
+				"Invalid dice face {}",
+				face
+			);
+			Ok(Face {
+				value,
+				symbol: Some(symbol.to_string()),
+			})
+		})
+		.collect::<Result<Vec<Face>>>()?;
+
+	ensure!(
+		faces.len() < (super::MAX_DICE_SIDES as usize),
+		"Must have < {} dice sides to roll. Tried: {}",
+		super::MAX_DICE_SIDES,
+		faces.len()
+	);
+	Ok(faces)
+}
@@ -0,0 +1,247 @@
+use super::{DiceInt, Settings};
+use anyhow::{anyhow, ensure, Result};
+use itertools::Itertools;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Largest dice a table can roll, and how deep tables can roll on other tables
+const MAX_TABLE_SIZE: DiceInt = super::MAX_DICE_SIDES;
+const MAX_TABLE_DEPTH: usize = 10;
+
+// A server's random tables by lowercase name
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Tables {
+	#[serde(default)]
+	pub tables: BTreeMap<String, Table>,
+}
+
+// Entries cover 1 to the table's size without gaps, so a larger range is a likelier result
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Table {
+	pub entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+	pub min: DiceInt,
+	pub max: DiceInt,
+	pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableRoll {
+	pub name: String,
+	pub size: DiceInt,
+	pub roll: DiceInt,
+	pub text: String, // with its dice rolled and nested tables rolled on
+}
+
+// Parses one entry per line eg "1-3: 2d6 gold" or "4: a magic sword".
+// A line without a range is the number after the previous line.
+pub fn parse_table(lines: &str) -> Result<Table> {
+	let mut entries: Vec<Entry> = vec![];
+	for line in lines.lines().map(str::trim).filter(|line| !line.is_empty()) {
+		let next = entries.last().map_or(1, |entry| entry.max + 1);
+		let (min, max, text) = match parse_range(line) {
+			Some((min, max, text)) => (min?, max?, text),
+			None => (next, next, line),
+		};
+		ensure!(
+			min == next && max >= min,
+			"Table entries must count up from 1 without gaps, expected {} next. Got: {}",
+			next,
+			line
+		);
+		ensure!(
+			max <= MAX_TABLE_SIZE,
+			"Tables can have at most {} results",
+			MAX_TABLE_SIZE
+		);
+		ensure!(!text.is_empty(), "Missing text for table entry {}", line);
+		entries.push(Entry {
+			min,
+			max,
+			text: text.to_string(),
+		});
+	}
+	ensure!(
+		!entries.is_empty(),
+		"Give a table entry on each line, eg 1-3: 2d6 gold"
+	);
+	Ok(Table { entries })
+}
+
+// The range before a colon, eg "1-3: text", if the line starts with one
+fn parse_range(line: &str) -> Option<(Result<DiceInt>, Result<DiceInt>, &str)> {
+	let (range, text) = line.split_once(':')?;
+	if range.is_empty()
+		|| !range
+			.chars()
+			.all(|c| c.is_ascii_digit() || c == '-' || c == ' ')
+	{
+		return None;
+	}
+	let number = |text: &str| {
+		text.trim()
+			.parse::<DiceInt>()
+			.map_err(|e| anyhow!("Can't parse table range {}, {}", range, e))
+	};
+	let (min, max) = range.split_once('-').unwrap_or((range, range));
+	Some((number(min), number(max), text.trim()))
+}
+
+impl Table {
+	#[must_use]
+	pub fn size(&self) -> DiceInt {
+		self.entries.last().map_or(0, |entry| entry.max)
+	}
+
+	fn entry(&self, roll: DiceInt) -> Option<&Entry> {
+		self.entries
+			.iter()
+			.find(|entry| (entry.min..=entry.max).contains(&roll))
+	}
+}
+
+impl Tables {
+	#[must_use]
+	pub fn get(&self, name: &str) -> Option<&Table> {
+		self.tables.get(&name.to_lowercase())
+	}
+
+	pub fn add(&mut self, name: &str, lines: &str) -> Result<()> {
+		ensure!(
+			!name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'),
+			"Table names must be letters, numbers and _. Tried: {}",
+			name
+		);
+		self.tables.insert(name.to_lowercase(), parse_table(lines)?);
+		Ok(())
+	}
+
+	pub fn roll(&self, name: &str, settings: &Settings, rng: &mut impl Rng) -> Result<TableRoll> {
+		self.roll_nested(name, settings, rng, 0)
+	}
+
+	fn roll_nested(
+		&self,
+		name: &str,
+		settings: &Settings,
+		rng: &mut impl Rng,
+		depth: usize,
+	) -> Result<TableRoll> {
+		ensure!(
+			depth < MAX_TABLE_DEPTH,
+			"Tables can only roll on other tables {} deep",
+			MAX_TABLE_DEPTH
+		);
+		let table = self
+			.get(name)
+			.ok_or_else(|| anyhow!("No table named {}", name))?;
+		let size = table.size();
+		let roll = rng.gen_range(1..=size);
+		let entry = table
+			.entry(roll)
+			.ok_or_else(|| anyhow!("No entry for {} on table {}", roll, name))?;
+		Ok(TableRoll {
+			name: name.to_lowercase(),
+			size,
+			roll,
+			text: self.fill(&entry.text, settings, rng, depth)?,
+		})
+	}
+
+	// Rolls the dice in an entry's text. [[name]] rolls on another table and [[expression]] rolls
+	// anything the roll command can, while plain dice like 2d6 or 1d4+1 don't need brackets.
+	fn fill(
+		&self,
+		text: &str,
+		settings: &Settings,
+		rng: &mut impl Rng,
+		depth: usize,
+	) -> Result<String> {
+		let mut filled = String::new();
+		let mut rest = text;
+		while let Some((before, after)) = rest.split_once("[[") {
+			let (inner, after) = after
+				.split_once("]]")
+				.ok_or_else(|| anyhow!("Missing ]] in table entry {}", text))?;
+			filled.push_str(&fill_dice(before, settings, rng)?);
+			let inner = inner.trim();
+			if self.get(inner).is_some() {
+				filled.push_str(&self.roll_nested(inner, settings, rng, depth + 1)?.text);
+			} else {
+				filled.push_str(
+					&super::roll_with_rng(inner, settings, rng)?
+						.value
+						.to_string(),
+				);
+			}
+			rest = after;
+		}
+		filled.push_str(&fill_dice(rest, settings, rng)?);
+		Ok(filled)
+	}
+}
+
+// Replaces words which are dice expressions with their results, eg "2d6 gold," to "7 gold,"
+fn fill_dice(text: &str, settings: &Settings, rng: &mut impl Rng) -> Result<String> {
+	text.split(' ')
+		.map(|word| {
+			let dice = word.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+			if !is_dice(dice) {
+				return Ok(word.to_string());
+			}
+			// the parser wants a count, so d6 is rolled as 1d6
+			let expression = if dice.starts_with('d') {
+				format!("1{dice}")
+			} else {
+				dice.to_string()
+			};
+			let value = super::roll_with_rng(&expression, settings, rng)?.value;
+			Ok(format!("{}{}", value, &word[dice.len()..]))
+		})
+		.collect::<Result<Vec<String>>>()
+		.map(|words| words.join(" "))
+}
+
+// Simple dice like d6, 2d6 or 1d4+1
+fn is_dice(word: &str) -> bool {
+	let is_number = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+	let Some((count, rest)) = word.split_once('d') else {
+		return false;
+	};
+	let sides = rest.split(['+', '-']).next().unwrap_or_default();
+	(count.is_empty() || is_number(count))
+		&& is_number(sides)
+		&& rest[sides.len()..].split(['+', '-']).skip(1).all(is_number)
+}
+
+impl fmt::Display for Entry {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.min == self.max {
+			write!(f, "{}: {}", self.min, self.text)
+		} else {
+			write!(f, "{}-{}: {}", self.min, self.max, self.text)
+		}
+	}
+}
+
+impl fmt::Display for Table {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.entries.iter().join("\n"))
+	}
+}
+
+impl TableRoll {
+	// eg "loot (4 on d4): a magic sword"
+	#[must_use]
+	pub fn to_markdown(&self) -> String {
+		format!(
+			"{} ({} on d{}): {}",
+			self.name, self.roll, self.size, self.text
+		)
+	}
+}
@@ -1,26 +1,109 @@
 use super::*;
 
 #[test]
-fn check_regex() {
-	assert!(ROLL_REGEX.is_match("(1d20)"));
-	assert!(ROLL_REGEX.is_match("1d20"));
-	assert!(ROLL_REGEX.is_match("2 + 1d20"));
-	assert!(ROLL_REGEX.is_match("4 + (5d11 / 2)"));
+fn parse_expression() -> Result<()> {
+	use parser::{parse, BinOp, Expr};
+
+	assert_eq!(
+		parse("(1d20)")?,
+		Expr::Group(Box::new(Expr::Dice("1d20".to_string())))
+	);
+	assert_eq!(parse("1d20")?, Expr::Dice("1d20".to_string()));
+	assert_eq!(
+		parse("2 + 1d20")?,
+		Expr::Binary(
+			BinOp::Add,
+			Box::new(Expr::Number(2.0)),
+			Box::new(Expr::Dice("1d20".to_string()))
+		)
+	);
+	assert_eq!(
+		parse("4 + (5d11 / 2)")?,
+		Expr::Binary(
+			BinOp::Add,
+			Box::new(Expr::Number(4.0)),
+			Box::new(Expr::Group(Box::new(Expr::Binary(
+				BinOp::Div,
+				Box::new(Expr::Dice("5d11".to_string())),
+				Box::new(Expr::Number(2.0))
+			))))
+		)
+	);
+	Ok(())
+}
+
+#[test]
+fn parse_precedence() -> Result<()> {
+	let mut rng = test_rng();
+	assert_eq!(roll_expressions("2 + 3 * 4", &mut rng)?.1, "14");
+	assert_eq!(roll_expressions("-2^2", &mut rng)?.1, "-4");
+	assert_eq!(roll_expressions("2^3^2", &mut rng)?.1, "512");
+	assert_eq!(roll_expressions("15 // 2", &mut rng)?.1, "7");
+	assert_eq!(roll_expressions("-7 // 2 * 2", &mut rng)?.1, "-8");
+	// totals round down, but fractions last until the end of the expression
+	assert_eq!(roll_expressions("7 / 2", &mut rng)?.1, "3");
+	assert_eq!(roll_expressions("ceil(7 / 2)", &mut rng)?.1, "4");
+	assert_eq!(roll_expressions("round(7 / 2)", &mut rng)?.1, "4");
+	assert_eq!(roll_expressions("-7 / 2", &mut rng)?.1, "-4");
+	assert_eq!(roll_expressions("10 / 4 * 2", &mut rng)?.1, "5");
+	assert_eq!(roll_expressions("2.3 * 100", &mut rng)?.1, "230");
+	assert_eq!(roll_expressions("1 / 3", &mut rng)?.1, "0");
+	assert!(roll_expressions("1 // 0", &mut rng).is_err());
+	assert_eq!(roll_expressions("10 - 4 - 3", &mut rng)?.1, "3");
+	assert_eq!(
+		roll_expressions("max(1, 5, 3) + min(2, 4)", &mut rng)?.1,
+		"7"
+	);
+	assert_eq!(roll_expressions("floor(7 / 2)", &mut rng)?.1, "3");
+	Ok(())
+}
+
+#[test]
+fn parse_errors() {
+	assert!(roll_expression("", &Settings::default()).is_err());
+	assert!(roll_expression("(1d20", &Settings::default()).is_err());
+	assert!(roll_expression("1d20)", &Settings::default()).is_err());
+	assert!(roll_expression("1d20 +", &Settings::default()).is_err());
+	assert!(roll_expression("foo(1)", &Settings::default()).is_err());
+	assert!(roll_expression("max()", &Settings::default()).is_err());
+	assert!(roll_expression("abs(1, 2)", &Settings::default()).is_err());
+	assert!(roll_expression("1 / 0", &Settings::default()).is_err());
+}
+
+#[test]
+fn nested_dice() -> Result<()> {
+	assert_eq!(
+		roll_expressions("max(1d1, (2d1 * 2))", &mut test_rng())?,
+		("max([1], ([1, 1] * 2))".to_string(), "4".to_string())
+	);
+	Ok(())
 }
 
 #[test]
 fn dice_roll_from_str() -> Result<()> {
 	assert_eq!(
-		DiceRoll::from_str("1d1", &mut rand::thread_rng())?,
+		DiceRoll::from_str(
+			"1d1",
+			&Settings::default(),
+			&mut Budget::default(),
+			&mut rand::thread_rng()
+		)?,
 		DiceRoll {
 			options: Options {
 				number_of_dice: 1,
 				dice_sides: vec![1],
+				symbols: vec![],
 				explode: None,
-				min: None,
-				max: None
+				keep: None,
+				reroll: None,
+				count: None,
+				filters: vec![],
+				crit: None,
+				fumble: None,
+				percentile: None,
+				fate: None
 			},
-			rolls: vec![1]
+			rolls: vec![Die::new(1)]
 		}
 	);
 	Ok(())
@@ -32,25 +115,23 @@ fn test_rng() -> impl Rng {
 }
 
 fn roll_expressions(msg: &str, rng: &mut impl Rng) -> Result<(String, String)> {
-	super::roll_expressions(msg, rng).map(|(_, dice, vals)| (dice, vals))
+	let result = roll_with_rng(msg, &Settings::default(), rng)?;
+	Ok((result.rolled.to_string(), result.value.to_string()))
 }
 
 #[test]
 fn roll_expression_simple() -> Result<()> {
 	assert_eq!(
 		roll_expressions("(1d1+1d1)", &mut test_rng())?,
-		("([1]+[1])".to_string(), "(1+1)".to_string())
+		("([1] + [1])".to_string(), "2".to_string())
 	);
 	assert_eq!(
 		roll_expressions("(1d1 + 1d1)", &mut test_rng())?,
-		("([1] + [1])".to_string(), "(1 + 1)".to_string())
+		("([1] + [1])".to_string(), "2".to_string())
 	);
 	assert_eq!(
 		roll_expressions("(5d11<5)", &mut test_rng())?,
-		(
-			"([~~7~~, 2, ~~9~~, ~~7~~, 3])".to_string(),
-			"(5)".to_string()
-		)
+		("([~~7~~, 2, ~~9~~, ~~7~~, 3])".to_string(), "5".to_string())
 	);
 	Ok(())
 }
@@ -60,7 +141,7 @@ fn roll_expression_exploded() -> Result<()> {
 	assert_eq!(
 		roll_expressions("11d3!", &mut test_rng())?,
 		(
-			"[2, 1, 1, 3, 2, 1, 1, 2, 2, 1, 2, 3, 2]".to_string(),
+			"[2, 1, 1, 3, 2, 1, 1, 2, 2, 1, 2, 3!, 2!]".to_string(),
 			"23".to_string()
 		)
 	);
@@ -72,41 +153,376 @@ fn roll_expression_compounded() -> Result<()> {
 	assert_eq!(
 		roll_expressions("11d3!!", &mut test_rng())?,
 		(
-			"[2, 1, 1, 5, 1, 1, 2, 2, 1, 2, 5]".to_string(),
+			"[2, 1, 1, 5!, 1, 1, 2, 2, 1, 2, 5!]".to_string(),
 			"23".to_string()
 		)
 	);
 	Ok(())
 }
 
+#[test]
+fn roll_conditional_explosions() -> Result<()> {
+	assert_eq!(
+		roll_expressions("10d10!>8", &mut test_rng())?,
+		(
+			"[2, 2, 6, 3, 2, 6, 1, 5, 10, 9, 5!, 5!]".to_string(),
+			"56".to_string()
+		)
+	);
+	assert_eq!(
+		roll_expressions("10d10!p>8", &mut test_rng())?.0,
+		"[2, 2, 6, 3, 2, 6, 1, 5, 10, 9, 4!, 4!]"
+	);
+	assert_eq!(
+		roll_expressions("10d10!>8l1", &mut test_rng())?.0,
+		"[2, 2, 6, 3, 2, 6, 1, 5, 10, 9, 5!]"
+	);
+	assert_eq!(
+		roll_expressions("10d10!!>8", &mut test_rng())?.0,
+		"[2, 2, 6, 3, 2, 6, 1, 5, 24!, 5]"
+	);
+	assert_eq!(roll_expressions("1d1!l5", &mut test_rng())?.1, "6");
+	assert!(roll_expression("1d6l2", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_comparisons() -> Result<()> {
+	assert_eq!(roll_expressions("10d10>=6", &mut test_rng())?.1, "31");
+	assert_eq!(roll_expressions("10d10=6", &mut test_rng())?.1, "12");
+	assert_eq!(roll_expressions("10d10<>2s", &mut test_rng())?.1, "7");
+	assert_eq!(roll_expressions("10d10>=9sf<=1", &mut test_rng())?.1, "1");
+	assert_eq!(roll_expressions("10d10!>=9", &mut test_rng())?.1, "56");
+
+	let explode_on = |msg| -> Result<Option<Condition>> {
+		Ok(options::parse(msg, &Settings::default())?
+			.explode
+			.map(|it| it.condition))
+	};
+	let condition = |compare, value| Some(Condition { compare, value });
+	assert_eq!(explode_on("1d6!=1")?, condition(Compare::Equal, 1));
+	assert_eq!(explode_on("1d6!!=1")?, condition(Compare::Equal, 1));
+	assert_eq!(explode_on("1d6!!!=1")?, condition(Compare::NotEqual, 1));
+	assert_eq!(explode_on("1d6!<=2")?, condition(Compare::LessOrEqual, 2));
+	Ok(())
+}
+
+#[test]
+fn roll_nested_dice() -> Result<()> {
+	use parser::{parse, Expr, NestedDice};
+
+	assert_eq!(
+		parse("(2+1)d8kh1")?,
+		Expr::NestedDice(Box::new(NestedDice {
+			count: Expr::Group(Box::new(Expr::Binary(
+				parser::BinOp::Add,
+				Box::new(Expr::Number(2.0)),
+				Box::new(Expr::Number(1.0))
+			))),
+			sides: None,
+			options: "8kh1".to_string(),
+		}))
+	);
+	assert_eq!(
+		roll_expressions("(1d4)d6", &mut test_rng())?,
+		("([3])d6 → 3d6 [1, 5, 4]".to_string(), "10".to_string())
+	);
+	assert_eq!(
+		roll_expressions("1d(2d6) + 1", &mut test_rng())?,
+		("1d([4, 1]) → 1d5 [2] + 1".to_string(), "3".to_string())
+	);
+	assert!(roll_expression("(1d1 / 2)d6", &Settings::default()).is_err());
+	assert!(roll_expression(&format!("({MAX_ROLLED_DICE})d6"), &Settings::default()).is_err());
+	assert!(roll_expression("1d(1d2 * 10000)", &Settings::default()).is_err());
+
+	assert_close(
+		expression_distribution("(1d2)d6", &Settings::default())?.mean(),
+		5.25,
+	);
+	assert_close(
+		expression_distribution("1d(1d2 * 2)", &Settings::default())?.mean(),
+		2.0,
+	);
+	Ok(())
+}
+
+#[test]
+fn roll_labels_and_comments() -> Result<()> {
+	use parser::{parse_statement, BinOp, Expr, Statement};
+
+	assert_eq!(
+		parse_statement("1d20[to hit] # attack")?,
+		Statement {
+			expr: Expr::Labeled(
+				Box::new(Expr::Dice("1d20".to_string())),
+				"to hit".to_string()
+			),
+			target: None,
+			comment: Some("attack".to_string())
+		}
+	);
+
+	// a label after a modifier goes with the dice before it
+	let dice = |dice: &str| Box::new(Expr::Dice(dice.to_string()));
+	assert_eq!(
+		parse_statement("1d20+5 [to hit] + 1d4 [bless]")?.expr,
+		Expr::Binary(
+			BinOp::Add,
+			Box::new(Expr::Binary(
+				BinOp::Add,
+				Box::new(Expr::Labeled(dice("1d20"), "to hit".to_string())),
+				Box::new(Expr::Number(5.0))
+			)),
+			Box::new(Expr::Labeled(dice("1d4"), "bless".to_string()))
+		)
+	);
+	assert_eq!(
+		parse_statement("(1d20+5) [to hit]")?.expr,
+		Expr::Labeled(
+			Box::new(Expr::Group(Box::new(Expr::Binary(
+				BinOp::Add,
+				dice("1d20"),
+				Box::new(Expr::Number(5.0))
+			)))),
+			"to hit".to_string()
+		)
+	);
+	assert_eq!(
+		parse_statement("5 [bonus]")?.expr,
+		Expr::Labeled(Box::new(Expr::Number(5.0)), "bonus".to_string())
+	);
+
+	let result = roll_with_rng(
+		"1d20+5 [to hit] + 1d4 [bless] # attack the goblin",
+		&Settings::default(),
+		&mut test_rng(),
+	)?;
+	assert_eq!(
+		result.to_markdown(),
+		"attack the goblin: [12] *to hit* + 5 + [1] *bless* => **18**"
+	);
+	assert_eq!(
+		result.to_plain(),
+		"attack the goblin: [12] [to hit] + 5 + [1] [bless] => 18"
+	);
+	assert!(roll_expression("1d20 [to hit", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_several_expressions() -> Result<()> {
+	assert_eq!(
+		parser::split_expressions("1d20+7; 2d6+4, max(1d4, 2) # a, b; 3d{1,2}"),
+		vec!["1d20+7", " 2d6+4", " max(1d4, 2) # a, b", " 3d{1,2}"]
+	);
+	assert_eq!(
+		roll_expression("1d1+1; 2d1, 1d1 # x, y;", &Settings::default())?,
+		"[1] + 1 => **2**\n[1, 1] => **2**\nx, y: **1**"
+	);
+	assert!(roll_expression(";", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_repeats() -> Result<()> {
+	assert_eq!(
+		roll_expression("3x(2d1)", &Settings::default())?,
+		"3x([1, 1]; [1, 1]; [1, 1]) => **[2, 2, 2]**"
+	);
+	assert_eq!(
+		roll_expression("sum(repeat(1d1 + 1, 4)) + 1", &Settings::default())?,
+		"sum(4x([1] + 1; [1] + 1; [1] + 1; [1] + 1)) + 1 => **9**"
+	);
+	assert_eq!(
+		roll_expression("sort(2x(1d1), 3, 2x(2x(2)))", &Settings::default())?,
+		"sort(2x([1]; [1]), 3, 2x(2x(2; 2); 2x(2; 2))) => **[1, 1, 2, 2, 2, 2, 3]**"
+	);
+	let mut rng = test_rng();
+	let (_, value) = roll_expressions("max(10x(1d10))", &mut rng)?;
+	assert_eq!(value, "10");
+	assert!(roll_expression("3x(1d6) + 1", &Settings::default()).is_err());
+	assert!(roll_expression("101x(1d6)", &Settings::default()).is_err());
+	// the limits cover the whole expression, so nested repeats can't multiply them
+	assert!(roll_expression("sum(10x(9x(1d6)))", &Settings::default()).is_ok());
+	assert_eq!(
+		roll_expression("sum(10x(10x(1d6)))", &Settings::default()).map_err(|e| e.to_string()),
+		Err(format!(
+			"Can repeat rolls at most {MAX_REPEATS} times in total, including repeats inside repeats"
+		))
+	);
+	assert!(roll_expression("sum(100x(100x(10x(100d6))))", &Settings::default()).is_err());
+	assert!(roll_expression("sum(100x(499d6))", &Settings::default()).is_ok());
+	assert!(roll_expression("sum(100x(499d6)) + 101d6", &Settings::default()).is_err());
+	assert!(roll_expression("0x(1d6)", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_crits() -> Result<()> {
+	assert_eq!(
+		roll_expression("2d1cs1", &Settings::default())?,
+		"[**1**, **1**] => **2** (crit)"
+	);
+	assert_eq!(
+		roll_expression("1d1cf<2 + 1d{3}cs>5", &Settings::default())?,
+		"[**1**] + [3] => **4** (fumble)"
+	);
+	// dropped dice don't count
+	let result = roll("2d{1,2}kh1cs1cf2", &Settings::default())?;
+	assert!(!(result.crit && result.fumble));
+
+	let settings = Settings {
+		crits: true,
+		..Settings::default()
+	};
+	let crit = Some(Condition {
+		compare: Compare::Equal,
+		value: 20,
+	});
+	assert_eq!(options::parse("1d20", &settings)?.crit, crit);
+	assert_eq!(options::parse("1d20cf<3", &settings)?.crit, None);
+	assert_eq!(options::parse("1d12", &settings)?.crit, None);
+	assert_eq!(roll_expression("1d{1,1}", &settings)?, "**1**");
+	assert!(roll_expression("1d20cs20cs19", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_targets() -> Result<()> {
+	use parser::parse_statement;
+
+	assert_eq!(
+		roll_expression("1d1+5 vs 5", &Settings::default())?,
+		"[1] + 5 => **6** >= 5: **success** by 1"
+	);
+	assert_eq!(
+		roll_expression("1d{10} + 2 VS 15 # stealth", &Settings::default())?,
+		"stealth: [10] + 2 => **12** >= 15: **failure** by 3"
+	);
+	assert_eq!(
+		roll_expression("1d{45} <= 45", &Settings::default())?,
+		"**45** <= 45: **success**"
+	);
+	assert_eq!(
+		roll_expression("1d{20} <= 45", &Settings::default())?,
+		"**20** <= 45: **hard success** by 25"
+	);
+	assert_eq!(
+		roll_expression("1d{9} <= 45", &Settings::default())?,
+		"**9** <= 45: **extreme success** by 36"
+	);
+	assert_eq!(
+		roll_expression("1d{10} vs 2d{3}", &Settings::default())?,
+		"**10** >= [3, 3] (6): **success** by 4"
+	);
+	let result = roll("1d{30} <= 40", &Settings::default())?;
+	let check = result.check.expect("has a target");
+	assert!(check.success);
+	assert_eq!(check.degree, Some(Degree::Regular));
+	assert!(roll("1d20 vs", &Settings::default()).is_err());
+	assert!(roll("1d20 => 5", &Settings::default()).is_err());
+	assert!(expression_distribution("1d20 vs 5", &Settings::default()).is_err());
+
+	// a single die's trailing comparison is a target even without spaces
+	assert_eq!(
+		roll_expression("1d{20}<=45", &Settings::default())?,
+		"**20** <= 45: **hard success** by 25"
+	);
+	assert_eq!(
+		roll_expression("1d{50}<=45 [spot]", &Settings::default())?,
+		"[50] *spot* => **50** <= 45: **failure** by 5"
+	);
+	assert_eq!(
+		parse_statement("2d20kh1>=15 # attack")?,
+		parse_statement("2d20kh1 >= 15 # attack")?
+	);
+	// pools and options keep their comparisons
+	assert!(parse_statement("10d10>=6")?.target.is_none());
+	assert!(parse_statement("1d6!>4")?.target.is_none());
+	assert!(parse_statement("1d20cs>19")?.target.is_none());
+	assert!(roll("1d100<=45 <= 50", &Settings::default()).is_err());
+	// elsewhere it filters the dice as it always has
+	assert!(parse_statement("1d20>10 + 1d6")?.target.is_none());
+	assert_eq!(roll_expression_value("(1d{5}>6)", &Settings::default())?, 0);
+	assert!(roll("max(1d20>=15, 10)", &Settings::default())?
+		.check
+		.is_none());
+	Ok(())
+}
+
+#[test]
+fn roll_advantage() -> Result<()> {
+	use parser::{parse, parse_statement, Expr};
+
+	assert_eq!(parse("adv")?, Expr::Dice("2d20kh1".to_string()));
+	assert_eq!(
+		parse_statement("1d20+3 DIS vs 10")?.expr,
+		parse("2d20kl1+3")?
+	);
+	assert_eq!(
+		parse_statement("1d6 + 1d20cs19 [attack] adv")?.expr,
+		parse("1d6 + 2d20kh1cs19 [attack]")?
+	);
+	assert_eq!(
+		roll_expressions("1d20+3 adv", &mut test_rng())?.0,
+		"[12, ~~4~~] + 3"
+	);
+	assert_eq!(parse_statement("1d20 [a] adv")?.expr, parse("2d20kh1 [a]")?);
+	assert_eq!(
+		parse_statement("1d20+5 [to hit] dis")?.expr,
+		parse("2d20kl1 [to hit] + 5")?
+	);
+	// a d20 which already keeps the right die is rolled twice as it is
+	assert_eq!(parse_statement("1d20kh1 adv")?.expr, parse("2d20kh1")?);
+	assert_eq!(parse_statement("1d20k1+3 adv")?.expr, parse("2d20k1+3")?);
+	assert_eq!(parse_statement("1d20kl1 DIS")?.expr, parse("2d20kl1")?);
+	assert_eq!(
+		roll_expressions("1d20kh1+3 adv", &mut test_rng())?.0,
+		"[12, ~~4~~] + 3"
+	);
+	for dice in ["1d20kl1", "1d20kh10", "1d20dl1"] {
+		assert_eq!(
+			parse_statement(&format!("{dice} adv")).map_err(|e| e.to_string()),
+			Err(format!(
+				"adv and dis already keep a die, so they can't be used with {dice}"
+			))
+		);
+	}
+	assert!(parse_statement("1d20kh1 dis").is_err());
+	assert!(parse("1d6 adv").is_err());
+	assert!(parse("1d200 dis").is_err());
+	assert!(parse("advantage").is_err());
+	Ok(())
+}
+
 #[test]
 fn roll_negative() {
-	assert!(roll_expression("-1d-1").is_err());
+	assert!(roll_expression("-1d-1", &Settings::default()).is_err());
 }
 
 #[test]
 fn roll_oversized_sides() {
-	assert!(roll_expression("2d4294967295").is_err());
+	assert!(roll_expression("2d4294967295", &Settings::default()).is_err());
 }
 
 #[test]
 fn roll_oversized_dice() {
-	assert!(roll_expression("4294967295d2").is_err());
+	assert!(roll_expression("4294967295d2", &Settings::default()).is_err());
 }
 
 #[test]
 fn roll_oversized_result() {
-	assert!(roll_expression("999999999d999999999").is_err());
+	assert!(roll_expression("999999999d999999999", &Settings::default()).is_err());
 }
 
 #[test]
 fn roll_oversized_unparseable() {
 	{
-		let roll = roll_expression("999999999999999999d1");
+		let roll = roll_expression("999999999999999999d1", &Settings::default());
 		assert!(roll.is_err(), "{:?}", roll);
 	}
 	{
-		let roll = roll_expression("1d999999999999999999999999");
+		let roll = roll_expression("1d999999999999999999999999", &Settings::default());
 		assert!(roll.is_err(), "{:?}", roll);
 	}
 }
@@ -114,15 +530,18 @@ fn roll_oversized_unparseable() {
 #[test]
 fn roll_barely_acceptably_sized() {
 	{
-		let roll = roll_expression(&format!("{}d1", MAX_ROLLED_DICE - 1));
+		let roll = roll_expression(&format!("{}d1", MAX_ROLLED_DICE - 1), &Settings::default());
 		assert!(roll.is_ok(), "{:?}", roll);
 	}
 	{
-		let roll = roll_expression(&format!("1d{}", MAX_DICE_SIDES - 1));
+		let roll = roll_expression(&format!("1d{}", MAX_DICE_SIDES - 1), &Settings::default());
 		assert!(roll.is_ok(), "{:?}", roll);
 	}
 	{
-		let roll = roll_expression(&format!("{}d{}", MAX_ROLLED_DICE - 1, MAX_DICE_SIDES - 1));
+		let roll = roll_expression(
+			&format!("{}d{}", MAX_ROLLED_DICE - 1, MAX_DICE_SIDES - 1),
+			&Settings::default(),
+		);
 		assert!(roll.is_ok(), "{:?}", roll);
 	}
 }
@@ -133,11 +552,18 @@ fn handle_overflow_in_sum() {
 		options: Options {
 			number_of_dice: 1,
 			dice_sides: vec![1],
+			symbols: vec![],
 			explode: None,
-			min: None,
-			max: None
+			keep: None,
+			reroll: None,
+			count: None,
+			filters: vec![],
+			crit: None,
+			fumble: None,
+			percentile: None,
+			fate: None
 		},
-		rolls: vec![DiceInt::max_value() - 1, 2]
+		rolls: vec![Die::new(DiceInt::max_value() - 1), Die::new(2)]
 	}
 	.val()
 	.is_err())
@@ -146,9 +572,27 @@ fn handle_overflow_in_sum() {
 #[test]
 fn roll_fudge() -> Result<()> {
 	assert_eq!(
-		roll_expressions("4dF", &mut test_rng())?,
-		("[0, -3, -3, 3]".to_string(), "-3".to_string())
+		roll_expressions("4dF3", &mut test_rng())?,
+		("[▢, −, −, +]".to_string(), "-3".to_string())
 	);
+	let result = roll_with_rng("4dF + 2", &Settings::default(), &mut test_rng())?;
+	assert_eq!(result.to_markdown(), "[▢, −, −, +] + 2 => **Average (+1)**");
+
+	let scaled = Settings {
+		scaled_fate: true,
+		..Settings::default()
+	};
+	assert_eq!(
+		options::parse("1dF", &scaled)?.fate,
+		Some(options::Fate::Scaled)
+	);
+	assert_eq!(
+		options::parse("1dF", &Settings::default())?.fate,
+		Some(options::Fate::Standard)
+	);
+	// the ladder is only for standard Fate dice
+	let result = roll_with_rng("4dF3", &Settings::default(), &mut test_rng())?;
+	assert!(!result.to_markdown().contains('('));
 	Ok(())
 }
 
@@ -156,20 +600,607 @@ fn roll_fudge() -> Result<()> {
 fn roll_expression_without_spaces() -> Result<()> {
 	assert_eq!(
 		roll_expressions("(1d1*10)", &mut test_rng())?,
-		("([1]*10)".to_string(), "(1*10)".to_string())
+		("([1] * 10)".to_string(), "10".to_string())
 	);
 	assert_eq!(
 		roll_expressions("(1d1/10)", &mut test_rng())?,
-		("([1]/10)".to_string(), "(1/10)".to_string())
+		("([1] / 10)".to_string(), "0".to_string())
 	);
 	assert_eq!(
 		roll_expressions("(1d1+10)", &mut test_rng())?,
-		("([1]+10)".to_string(), "(1+10)".to_string())
+		("([1] + 10)".to_string(), "11".to_string())
 	);
 	assert_eq!(
 		roll_expressions("(1d1-10)", &mut test_rng())?,
-		("([1]-10)".to_string(), "(1-10)".to_string())
+		("([1] - 10)".to_string(), "-9".to_string())
+	);
+
+	Ok(())
+}
+
+#[test]
+fn roll_keep_highest() -> Result<()> {
+	assert_eq!(
+		roll_expressions("4d6kh3", &mut test_rng())?,
+		("[4, ~~1~~, 5, 4]".to_string(), "13".to_string())
+	);
+	assert_eq!(
+		roll_expressions("4d6k3", &mut test_rng())?,
+		("[4, ~~1~~, 5, 4]".to_string(), "13".to_string())
+	);
+	Ok(())
+}
+
+#[test]
+fn roll_keep_lowest() -> Result<()> {
+	assert_eq!(
+		roll_expressions("2d20kl1", &mut test_rng())?,
+		("[~~12~~, 4]".to_string(), "4".to_string())
+	);
+	Ok(())
+}
+
+#[test]
+fn roll_drop() -> Result<()> {
+	assert_eq!(
+		roll_expressions("4d6dl1", &mut test_rng())?,
+		("[4, ~~1~~, 5, 4]".to_string(), "13".to_string())
+	);
+	assert_eq!(
+		roll_expressions("4d6dh2", &mut test_rng())?,
+		("[4, 1, ~~5~~, ~~4~~]".to_string(), "5".to_string())
+	);
+	Ok(())
+}
+
+#[test]
+fn roll_keep_more_than_rolled() -> Result<()> {
+	assert_eq!(
+		roll_expressions("2d6kh5", &mut test_rng())?,
+		("[4, 1]".to_string(), "5".to_string())
+	);
+	assert!(roll_expression("4d6kh1kl1", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_reroll_once() -> Result<()> {
+	assert_eq!(
+		roll_expressions("4d6r<3", &mut test_rng())?,
+		("[4, ~~1~~→5, 4, ~~2~~→1]".to_string(), "14".to_string())
+	);
+	// a single rerolled die still shows the roll it replaced
+	assert_eq!(
+		roll_expression("1d{5}r5", &Settings::default())?,
+		"[~~5~~→5] => **5**"
+	);
+	Ok(())
+}
+
+#[test]
+fn roll_reroll_until() -> Result<()> {
+	assert_eq!(
+		roll_expressions("6d3rr1", &mut test_rng())?,
+		(
+			"[2, ~~1~~→~~1~~→3, 2, ~~1~~→~~1~~→2, 2, ~~1~~→2]".to_string(),
+			"13".to_string()
+		)
+	);
+	assert!(roll_expression("1d6rr<7", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_endless_explosion() {
+	assert!(roll_expression("1d1!", &Settings::default()).is_err());
+	assert!(roll_expression("1d1!!", &Settings::default()).is_err());
+}
+
+#[test]
+fn roll_count_successes() -> Result<()> {
+	assert_eq!(
+		roll_expressions("10d10>7s", &mut test_rng())?,
+		(
+			"[~~2~~, ~~2~~, ~~6~~, ~~3~~, ~~2~~, ~~6~~, ~~1~~, ~~5~~, 10, 9]".to_string(),
+			"2".to_string()
+		)
+	);
+	assert_eq!(
+		roll_expressions("10d10>7sf1", &mut test_rng())?,
+		(
+			"[~~2~~, ~~2~~, ~~6~~, ~~3~~, ~~2~~, ~~6~~, ~~1~~, ~~5~~, 10, 9]".to_string(),
+			"1".to_string()
+		)
+	);
+	assert_eq!(
+		roll_expressions("10d10>7f1s", &mut test_rng())?,
+		roll_expressions("10d10>7sf1", &mut test_rng())?
+	);
+	Ok(())
+}
+
+#[test]
+fn successes_summary() -> Result<()> {
+	let summary = |msg| -> Result<Option<String>> {
+		match roll_with_rng(msg, &Settings::default(), &mut test_rng())?.rolled {
+			Rolled::Dice(roll) => Ok(roll.successes_summary()),
+			_ => Ok(None),
+		}
+	};
+	assert_eq!(summary("10d10>7s")?, Some("2 successes".to_string()));
+	assert_eq!(summary("7d10>9sf1")?, Some("botch".to_string()));
+	assert_eq!(summary("10d10>7sf1")?, Some("1 success".to_string()));
+	assert_eq!(summary("10d10")?, None);
+	Ok(())
+}
+
+#[test]
+fn render_styles() -> Result<()> {
+	let result = roll_with_rng("4d6kh3 + 1", &Settings::default(), &mut test_rng())?;
+	assert_eq!(result.to_markdown(), "[4, ~~1~~, 5, 4] + 1 => **14**");
+	assert_eq!(result.to_plain(), "[4, (1), 5, 4] + 1 => 14");
+
+	let json: serde_json::Value = serde_json::from_str(&result.to_json()?)?;
+	assert_eq!(json["value"], 14.0);
+	assert_eq!(
+		json["rolled"]["binary"][1]["dice"]["rolls"][1]["dropped"],
+		true
+	);
+	Ok(())
+}
+
+#[test]
+fn exploded_dice_marked() -> Result<()> {
+	let exploded = |msg| -> Result<Vec<bool>> {
+		match roll_with_rng(msg, &Settings::default(), &mut test_rng())?.rolled {
+			Rolled::Dice(roll) => Ok(roll.rolls.iter().map(|it| it.exploded).collect()),
+			_ => Ok(vec![]),
+		}
+	};
+	assert_eq!(exploded("11d3!")?.iter().filter(|it| **it).count(), 2);
+	assert_eq!(exploded("11d3!!")?.iter().filter(|it| **it).count(), 2);
+	Ok(())
+}
+
+#[test]
+fn roll_custom_faces() -> Result<()> {
+	assert_eq!(
+		roll_expressions("3d{-1, 0, 1} + 1d{7}", &mut test_rng())?.0,
+		"[0, -1, -1] + [7]"
+	);
+	assert_close(
+		expression_distribution("1d{1,1,2}", &Settings::default())?.mean(),
+		4.0 / 3.0,
+	);
+	assert!(roll_expression("1d{1,2", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_named_dice() -> Result<()> {
+	let mut settings = Settings::default();
+	settings.add_dice("Hit", "miss, hit=1, crit=2")?;
+	assert!(settings.add_dice("hit", "1").is_err());
+
+	let result = roll_with_rng("4dHit", &settings, &mut test_rng())?;
+	assert_eq!(
+		result.to_markdown(),
+		"[hit, miss, miss, crit] => **2 miss, 1 hit, 1 crit**"
+	);
+	assert_eq!(result.value.to_string(), "3");
+	assert!(roll_expression("1dMiss", &settings).is_err());
+	Ok(())
+}
+
+fn assert_close(actual: f64, expected: f64) {
+	assert!(
+		(actual - expected).abs() < 1e-6,
+		"{} != {}",
+		actual,
+		expected
+	);
+}
+
+#[test]
+fn distribution_simple() -> Result<()> {
+	let dist = expression_distribution("2d6 + 1", &Settings::default())?;
+	assert_close(dist.mean(), 8.0);
+	assert_close(dist.std_dev(), (35.0_f64 / 6.0).sqrt());
+	assert_close(dist.at_least(8.0), 21.0 / 36.0);
+	assert_close(dist.min(), 3.0);
+	assert_close(dist.max(), 13.0);
+	assert_close(dist.percentile(0.5), 8.0);
+	assert_eq!(dist.possible_results(), 11);
+	Ok(())
+}
+
+#[test]
+fn distribution_options() -> Result<()> {
+	assert_close(
+		expression_distribution("4d6kh3", &Settings::default())?.mean(),
+		15869.0 / 1296.0,
+	);
+	assert_close(
+		expression_distribution("4d6dl1", &Settings::default())?.mean(),
+		15869.0 / 1296.0,
+	);
+	assert_close(
+		expression_distribution("2d20kl1", &Settings::default())?.mean(),
+		7.175,
+	);
+	assert_close(
+		expression_distribution("10d10>7s", &Settings::default())?.mean(),
+		3.0,
+	);
+	assert_close(
+		expression_distribution("10d10>7sf1", &Settings::default())?.mean(),
+		2.0,
+	);
+	assert_close(
+		expression_distribution("1d6>3", &Settings::default())?.mean(),
+		2.5,
+	);
+	assert_close(
+		expression_distribution("1d6r1", &Settings::default())?.mean(),
+		3.5 + 2.5 / 6.0,
+	);
+	assert_close(
+		expression_distribution("1d6rr1", &Settings::default())?.mean(),
+		4.0,
+	);
+	Ok(())
+}
+
+#[test]
+fn distribution_explosions() -> Result<()> {
+	assert_close(
+		expression_distribution("1d6!", &Settings::default())?.mean(),
+		4.2,
+	);
+	assert_close(
+		expression_distribution("1d6!!", &Settings::default())?.mean(),
+		4.2,
+	);
+	assert_close(
+		expression_distribution("3d6!", &Settings::default())?.mean(),
+		12.6,
+	);
+	assert_close(
+		expression_distribution("1d6!>4", &Settings::default())?.mean(),
+		5.25,
+	);
+	assert_close(
+		expression_distribution("1d6!p", &Settings::default())?.mean(),
+		4.0,
+	);
+	assert_close(
+		expression_distribution("1d6!l1", &Settings::default())?.mean(),
+		3.5 * 7.0 / 6.0,
+	);
+	assert_close(
+		expression_distribution("1d1!l5", &Settings::default())?.mean(),
+		6.0,
 	);
+	// limits are shared by every dice in the term
+	assert_close(
+		expression_distribution("3d4!l2", &Settings::default())?.mean(),
+		9.599_609_375,
+	);
+	assert_close(
+		expression_distribution("3d4!pl2", &Settings::default())?.mean(),
+		8.759_765_625,
+	);
+	assert_close(
+		expression_distribution("3d4!!l2", &Settings::default())?.mean(),
+		9.599_609_375,
+	);
+	let capped = expression_distribution("3d1!l4", &Settings::default())?;
+	assert_eq!((capped.min(), capped.max()), (7.0, 7.0));
+	assert!(expression_distribution("5d6!l3", &Settings::default()).is_ok());
+	assert!(expression_distribution("1d1!", &Settings::default()).is_err());
+	// keeping or dropping exploded dice isn't supported
+	assert!(expression_distribution("4d6!kh3", &Settings::default()).is_err());
+	assert!(expression_distribution("5d6!l3kh3", &Settings::default()).is_err());
+	assert!(expression_distribution("4d6!!l2dl1", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn distribution_functions() -> Result<()> {
+	assert_close(
+		expression_distribution("max(1d20, 1d20)", &Settings::default())?.mean(),
+		13.825,
+	);
+	assert_close(
+		expression_distribution("-1d4", &Settings::default())?.mean(),
+		-2.5,
+	);
+	assert!(expression_distribution("1d20 / 0", &Settings::default()).is_err());
+	// totals round down, as when rolling
+	assert_close(
+		expression_distribution("1d4 / 2", &Settings::default())?.mean(),
+		1.0,
+	);
+	assert!(expression_distribution("30d100 * 30d100", &Settings::default()).is_err());
+	assert_close(
+		expression_distribution("sum(3x(1d6))", &Settings::default())?.mean(),
+		10.5,
+	);
+	assert_close(
+		expression_distribution("max(repeat(1d20, 2))", &Settings::default())?.mean(),
+		13.825,
+	);
+	assert!(expression_distribution("2x(1d6)", &Settings::default()).is_err());
+	assert!(expression_distribution("sum((1d2)x(1d6))", &Settings::default()).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_percentile() -> Result<()> {
+	let mut rng = test_rng();
+	assert_eq!(
+		roll_expressions("1d%", &mut rng)?,
+		("tens [70] units [1]".to_string(), "71".to_string())
+	);
+	// bonus dice keep the lowest result, penalty dice the highest
+	assert_eq!(
+		roll_expressions("1d%b2", &mut rng)?,
+		(
+			"tens [~~80~~, ~~50~~, 20] units [1]".to_string(),
+			"21".to_string()
+		)
+	);
+	assert_eq!(
+		roll_expressions("1d%p1", &mut rng)?,
+		("tens [90, ~~10~~] units [2]".to_string(), "92".to_string())
+	);
+	assert_eq!(
+		roll_expressions("1d%b1p1", &mut rng)?,
+		("tens [50] units [4]".to_string(), "54".to_string())
+	);
+
+	// d% is short for 1d%
+	assert_eq!(parser::parse("d%")?, parser::parse("1d%")?);
+	assert_eq!(parser::parse("d%b1 + 5")?, parser::parse("1d%b1 + 5")?);
+	assert_eq!(
+		roll_expressions("d%p1", &mut test_rng())?,
+		roll_expressions("1d%p1", &mut test_rng())?
+	);
+	assert!(parser::parse_statement("d%<=45")?.target.is_some());
+	assert!(roll_expression("d20", &Settings::default()).is_err());
+	assert!(roll_expression("2d%", &Settings::default()).is_err());
+	assert!(roll_expression("1d%kh1", &Settings::default()).is_err());
+	assert!(roll_expression("1d20b1", &Settings::default()).is_err());
+	assert!(roll_expression("1d%b11", &Settings::default()).is_err());
+
+	assert_close(
+		expression_distribution("1d%", &Settings::default())?.mean(),
+		50.5,
+	);
+	assert_close(
+		expression_distribution("1d%b1", &Settings::default())?.mean(),
+		34.0,
+	);
+	assert_close(
+		expression_distribution("1d%p1", &Settings::default())?.mean(),
+		67.0,
+	);
+	Ok(())
+}
+
+#[test]
+fn roll_savage_worlds() -> Result<()> {
+	let mut rng = test_rng();
+	assert_eq!(
+		savage::trait_roll("d8+1", &Settings::default(), &mut rng)?.to_markdown(),
+		"max([2] *trait*, [1] *wild*) + 1 => **3** >= 4: **failure** by 1"
+	);
+	assert_eq!(
+		savage::trait_roll("1d4 vs 8 # fight", &Settings::default(), &mut rng)?.to_markdown(),
+		"fight: max([4, 3!] *trait*, [2] *wild*) => **7** >= 8: **failure** by 1"
+	);
+	let result = savage::trait_roll("d12+10", &Settings::default(), &mut rng)?;
+	assert_eq!(result.raises, 2);
+	assert_eq!(
+		result.to_markdown(),
+		"max([2] *trait*, [4] *wild*) + 10 => **14** >= 4: **success** by 10, 2 raises"
+	);
+
+	let critical_failure = (0..200)
+		.map(|_| savage::trait_roll("d4+5", &Settings::default(), &mut rng))
+		.collect::<Result<Vec<_>>>()?
+		.into_iter()
+		.find(|it| it.critical_failure)
+		.expect("a critical failure in 200 rolls");
+	assert_eq!(critical_failure.raises, 0);
+	assert!(critical_failure.result.check.is_none());
+	assert!(critical_failure
+		.to_markdown()
+		.ends_with("=> **6**, **critical failure**"));
+
+	assert!(savage::trait_roll("8", &Settings::default(), &mut rng).is_err());
+	assert!(savage::trait_roll("dF", &Settings::default(), &mut rng).is_err());
+	Ok(())
+}
 
+#[test]
+fn roll_narrative() -> Result<()> {
+	let mut rng = test_rng();
+	// the two advantages and two threats cancel
+	assert_eq!(
+		narrative::roll("2a1p1d1c", &mut rng)?.to_markdown(),
+		"🟩✅ 🟩✅⬆️ 🟨✅⬆️ 🟪⬇️ 🟥⬇️ => **Success**: 3 success ✅"
+	);
+	// the triumph is also a success
+	let result = narrative::roll("b s 3P", &mut rng)?;
+	assert_eq!(
+		result.to_markdown(),
+		"🟦▫️ ⬛❌ 🟨🏆 🟨✅✅ 🟨✅✅ => **Success**: 4 success ✅, 1 triumph 🏆"
+	);
+	assert_eq!(result.net.success, 4);
+	assert_eq!(result.net.failure, 0);
+	assert!(!narrative::roll("1c", &mut rng)?.succeeded());
+
+	assert!(narrative::roll("", &mut rng).is_err());
+	assert!(narrative::roll("2x", &mut rng).is_err());
+	assert!(narrative::roll("2a3", &mut rng).is_err());
+	assert!(narrative::roll("101a", &mut rng).is_err());
+	assert!(narrative::roll("99999999999a", &mut rng).is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_moves() -> Result<()> {
+	let settings = Settings::default();
+	let pbta = settings.find_move("pbta").expect("pbta is built in");
+	let mut rng = test_rng();
+	assert_eq!(
+		moves::roll("pbta", &pbta, "+2", &settings, &mut rng)?.to_markdown(),
+		"[4, 1] + 2 => **7**: **Weak hit**"
+	);
+	let blades = settings.find_move("Blades").expect("blades is built in");
+	let outcomes = (0..100)
+		.map(|_| moves::roll("blades", &blades, "3", &settings, &mut rng))
+		.collect::<Result<Vec<_>>>()?;
+	assert!(outcomes
+		.iter()
+		.any(|it| it.outcome.as_deref() == Some("Critical")));
+	assert!(outcomes.iter().all(|it| it.outcome.is_some()));
+
+	// a bare number is a modifier, not more dice
+	assert_eq!(
+		moves::roll("pbta", &pbta, "2", &settings, &mut test_rng())?.to_markdown(),
+		"[4, 1] + 2 => **7**: **Weak hit**"
+	);
+	assert!(moves::roll("pbta", &pbta, "d6", &settings, &mut rng).is_err());
+	// a pool of 0 rolls 2d6 and keeps the lowest, which never crits
+	let zero = (0..100)
+		.map(|_| moves::roll("blades", &blades, "0", &settings, &mut rng))
+		.collect::<Result<Vec<_>>>()?;
+	assert!(zero
+		.iter()
+		.all(|it| it.outcome.as_deref() != Some("Critical")
+			&& it.result.expression.starts_with("2d6kl1")));
+	assert!(moves::roll("blades", &blades, "", &settings, &mut rng).is_err());
+	assert!(moves::roll("blades", &blades, "-1", &settings, &mut rng).is_err());
+
+	let mut settings = Settings::default();
+	settings.add_move(
+		"Hack",
+		"2d6{} 6-: Miss, 7-9: Weak hit, 10+: Strong hit, 6x2: Boxcars",
+	)?;
+	assert_eq!(
+		settings.find_move("blades").map(|it| it.to_string()),
+		Some(
+			"{}d6kh1|2d6kl1 1-3: Bad outcome, 4-5: Partial success, 6: Full success, 6x2: Critical"
+				.to_string()
+		)
+	);
+	assert!(settings.add_move("bad", "2d6{}|2d6kl1 7+: Hit").is_err());
+	assert_eq!(
+		settings.find_move("hack").map(|it| it.to_string()),
+		Some("2d6{} 6-: Miss, 7-9: Weak hit, 10+: Strong hit, 6x2: Boxcars".to_string())
+	);
+	assert!(settings.add_move("hack", "2d6").is_err());
+	assert!(settings.add_move("hack", "2d6 7-9 Weak hit").is_err());
+	assert!(settings.add_move("hack2", "2d6 10+: Hit").is_err());
+	Ok(())
+}
+
+#[test]
+fn roll_tables() -> Result<()> {
+	let settings = Settings::default();
+	let mut data = Tables::default();
+	data.add("Gems", "1-2: 1d1+1 rubies\n3: [[1d1*10]] pearls")?;
+	data.add("loot", "1: d1 gold, and 2d1 silver\n[[gems]]")?;
+	assert_eq!(
+		data.get("loot").map(Table::to_string),
+		Some("1: d1 gold, and 2d1 silver\n2: [[gems]]".to_string())
+	);
+	let mut rng = test_rng();
+	let rolls = (0..20)
+		.map(|_| data.roll("loot", &settings, &mut rng).map(|it| it.text))
+		.collect::<Result<Vec<_>>>()?;
+	assert!(rolls
+		.iter()
+		.all(|text| ["1 gold, and 2 silver", "2 rubies", "10 pearls"].contains(&text.as_str())));
+	assert_eq!(itertools::Itertools::unique(rolls.iter()).count(), 3);
+	assert_eq!(
+		data.roll("loot", &settings, &mut test_rng())?.to_markdown(),
+		"loot (2 on d2): 2 rubies"
+	);
+
+	assert!(data.add("bad", "1: a\n3: b").is_err());
+	assert!(data.add("bad", "2-1: a").is_err());
+	assert!(data.add("bad", "").is_err());
+	data.add("loop", "[[loop]]")?;
+	assert!(data.roll("loop", &settings, &mut rng).is_err());
+	assert!(data.roll("missing", &settings, &mut rng).is_err());
+	Ok(())
+}
+
+#[test]
+fn draw_cards() -> Result<()> {
+	use std::collections::HashSet;
+
+	let mut rng = test_rng();
+	assert_eq!(DeckKind::Standard.cards().len(), 52);
+	assert_eq!(DeckKind::parse("jokers")?.cards().len(), 54);
+	assert_eq!(DeckKind::parse("Tarot")?.cards().len(), 78);
+	assert!(DeckKind::parse("one card").is_err());
+	assert!(DeckKind::parse("a, , b").is_err());
+
+	// a new deck shuffles itself, and cards aren't drawn twice
+	let mut deck = Deck::default();
+	let next = deck.peek(2, &mut rng)?.to_vec();
+	assert_eq!(deck.draw(2, &mut rng)?, next);
+	let drawn: HashSet<String> = (0..10)
+		.map(|_| deck.draw(5, &mut rng))
+		.collect::<Result<Vec<_>>>()?
+		.into_iter()
+		.flatten()
+		.chain(next)
+		.collect();
+	assert_eq!(drawn.len(), 52);
+	assert!(deck.draw(1, &mut rng).is_err());
+	assert_eq!(deck.describe(&[]), " (0 left)");
+
+	let first = deck.drawn[0].clone();
+	assert_eq!(deck.return_cards(&[&first], &mut rng)?, 1);
+	assert_eq!(deck.draw(1, &mut rng)?, vec![first.clone()]);
+	assert!(deck.return_cards(&["Red Joker"], &mut rng).is_err());
+	assert_eq!(deck.return_cards(&[], &mut rng)?, 52);
+	assert!(deck.drawn.is_empty());
+
+	deck.shuffle(Some(DeckKind::parse("Deck, of, Many, Things")?), &mut rng);
+	assert_eq!(deck.pile.len(), 4);
+	assert!(deck.draw(5, &mut rng).is_err());
+	assert!(deck.draw(0, &mut rng).is_err());
+	Ok(())
+}
+
+#[test]
+fn random_utilities() -> Result<()> {
+	let mut rng = test_rng();
+	assert_eq!(random::choose("only", &mut rng)?, "only");
+	assert_eq!(random::choose("0:never, 5: always", &mut rng)?, "always");
+	assert!(["a", "b c"].contains(&random::choose("a, b c", &mut rng)?.as_str()));
+	assert!(random::choose("", &mut rng).is_err());
+	assert!(random::choose("0:a, 0:b", &mut rng).is_err());
+
+	let mut order = random::shuffle("Alice Bob Carol", &mut rng)?;
+	order.sort();
+	assert_eq!(order, vec!["Alice", "Bob", "Carol"]);
+	assert!(random::shuffle("a, , b", &mut rng).is_err());
+
+	let flips = random::flip(10, &mut rng)?;
+	assert_eq!(flips.coins.len(), 10);
+	assert_eq!(
+		flips.to_markdown(),
+		"T, H, H, T, H, T, T, H, T, T => **4 heads**, 6 tails"
+	);
+	assert!(random::flip(1, &mut rng)?.to_markdown().ends_with("s**"));
+	assert!(random::flip(0, &mut rng).is_err());
+	assert!(random::flip(501, &mut rng).is_err());
 	Ok(())
 }